@@ -0,0 +1,102 @@
+//! Accounting of a single linearly accruing allowance.
+//!
+//! An allowance grows at a constant rate from `t0` on. Whatever is paid out or
+//! locked is added to `locked`, so the currently available amount is
+//! everything accrued since `t0`, plus what has been unlocked explicitly, minus
+//! `locked`.
+//!
+//! Amounts and rates are in the smallest unit of the token, which is yocto NEAR
+//! for NEAR itself.
+//!
+//! Each allowance has a ceiling. Accrual stops while the available amount
+//! sits at the ceiling. To make that stick, every state change forfeits the
+//! accrued amount above the ceiling by adding it to `locked`. Views only need
+//! to clamp, since nothing above the ceiling can have been spent since the last
+//! state change.
+
+use crate::error::Error;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{env, Balance};
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Allowance {
+    /// Initial timestamp (ns) from which the allowance is computed from.
+    t0: u64,
+    /// Amount either retrieved or forgone.
+    locked: u128,
+    /// Amount made available on top of the linear accrual.
+    unlocked: u128,
+}
+
+impl Allowance {
+    pub(crate) fn new() -> Self {
+        Self {
+            t0: env::block_timestamp(),
+            locked: 0,
+            unlocked: 0,
+        }
+    }
+
+    /// Allowance as stored by the first version of teller.
+    pub(crate) fn from_parts(t0: u64, locked: u128) -> Self {
+        Self {
+            t0,
+            locked,
+            unlocked: 0,
+        }
+    }
+
+    /// Available balance, with `per_second` as rate in the smallest unit.
+    pub(crate) fn available(&self, per_second: u128, max: Balance) -> Balance {
+        self.uncapped(per_second).min(max)
+    }
+
+    /// Take `yocto` out of the available balance, or fail without changes.
+    pub(crate) fn try_lock(
+        &mut self,
+        yocto: Balance,
+        per_second: u128,
+        max: Balance,
+    ) -> Result<(), Error> {
+        let uncapped = self.uncapped(per_second);
+        let available = self.available(per_second, max);
+        if available < yocto {
+            Err(Error::NotEnoughHot)
+        } else {
+            let forfeited = uncapped - available;
+            self.locked += forfeited + yocto;
+            Ok(())
+        }
+    }
+
+    /// Move `t0` to now without changing the available balance.
+    ///
+    /// Call this before changing the rate, so that everything accrued so far
+    /// is kept exactly as it was accrued at the old rate.
+    pub(crate) fn checkpoint(&mut self, per_second: u128, max: Balance) {
+        let uncapped = self.uncapped(per_second);
+        self.locked += uncapped - self.available(per_second, max);
+        self.unlocked += self.accrued(per_second);
+        self.t0 = env::block_timestamp();
+    }
+
+    /// Make `yocto` available immediately. The ceiling still applies.
+    pub(crate) fn unlock(&mut self, yocto: Balance) {
+        self.unlocked += yocto;
+    }
+
+    fn uncapped(&self, per_second: u128) -> Balance {
+        self.accrued(per_second) + self.unlocked - self.locked
+    }
+
+    /// Amount accrued since `t0`.
+    ///
+    /// Whole seconds and the remaining nanoseconds are multiplied separately,
+    /// multiplying by the nanoseconds first would overflow within days at high
+    /// rates.
+    fn accrued(&self, per_second: u128) -> Balance {
+        let ns = (env::block_timestamp() - self.t0) as u128;
+        let giga = 10u128.pow(9);
+        ns / giga * per_second + ns % giga * per_second / giga
+    }
+}
@@ -3,6 +3,39 @@
 pub(crate) enum Error {
     NotEnoughHot,
     ForeignAccountNotAllowed,
+    FullAccessKeyRequired,
+    DailyLimitExceeded,
+    UnknownPayment,
+    PaymentStillDelayed,
+    UnknownStakingPool,
+    StakingPoolAlreadyListed,
+    NotAFactoryStakingPool,
+    StakingPoolNotWhitelisted,
+    NotACallback,
+    StakingPoolAccountUnavailable,
+    StakingPoolWithdrawalNotReady,
+    RedelegationInProgress,
+    RedelegationToSamePool,
+    NoRedelegation,
+    NothingToRedelegate,
+    EmptyBatch,
+    DuplicateReceiver,
+    BatchTooLarge,
+    UnknownToken,
+    InvalidSchedule,
+    UnknownSchedule,
+    Frozen,
+    NoInheritance,
+    NotBeneficiary,
+    InheritanceNotDue,
+    NotGuardian,
+    UnknownRecovery,
+    AlreadyApproved,
+    RecoveryNotReady,
+    NotCoSigner,
+    PaymentNotApproved,
+    KeyTierCannotPayTokens,
+    ZeroKeyAllowance,
 }
 
 impl Error {
@@ -10,6 +43,43 @@ impl Error {
         match self {
             Error::NotEnoughHot => "not enough hot tokens",
             Error::ForeignAccountNotAllowed => "must be called by contract account",
+            Error::FullAccessKeyRequired => "must attach 1 yocto using a full access key",
+            Error::DailyLimitExceeded => "daily spending limit exceeded",
+            Error::UnknownPayment => "no pending payment with this id",
+            Error::PaymentStillDelayed => "payment cannot be released yet",
+            Error::UnknownStakingPool => "no staking pool with this index",
+            Error::StakingPoolAlreadyListed => "staking pool is already listed",
+            Error::NotAFactoryStakingPool => {
+                "must be a direct subaccount of the staking pool factory"
+            }
+            Error::StakingPoolNotWhitelisted => "staking pool is not whitelisted",
+            Error::NotACallback => "must be called as callback",
+            Error::StakingPoolAccountUnavailable => "could not read account from staking pool",
+            Error::StakingPoolWithdrawalNotReady => "unstaked balance cannot be withdrawn yet",
+            Error::RedelegationInProgress => "another redelegation is in progress",
+            Error::RedelegationToSamePool => "cannot redelegate to the same staking pool",
+            Error::NoRedelegation => "no redelegation in progress",
+            Error::NothingToRedelegate => "no unstaked balance to redelegate",
+            Error::EmptyBatch => "batch contains no payments",
+            Error::DuplicateReceiver => "receiver appears more than once in batch",
+            Error::BatchTooLarge => "batch contains too many payments",
+            Error::UnknownToken => "no fungible token with this index",
+            Error::InvalidSchedule => "period and count must be positive",
+            Error::UnknownSchedule => "no schedule with this id",
+            Error::Frozen => "spending is frozen until unfrozen with the full access key",
+            Error::NoInheritance => "no beneficiary configured",
+            Error::NotBeneficiary => "must be called by the beneficiary",
+            Error::InheritanceNotDue => "owner has not been inactive for long enough",
+            Error::NotGuardian => "must be called by a guardian",
+            Error::UnknownRecovery => "no recovery proposal with this id",
+            Error::AlreadyApproved => "already approved by this account",
+            Error::RecoveryNotReady => "recovery is not approved or still delayed",
+            Error::NotCoSigner => "must be called by a co-signer",
+            Error::PaymentNotApproved => "payment needs more co-signer approvals",
+            Error::KeyTierCannotPayTokens => {
+                "keys with their own allowance cannot pay fungible tokens"
+            }
+            Error::ZeroKeyAllowance => "key allowance of 0 would be unlimited",
         }
     }
 
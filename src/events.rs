@@ -0,0 +1,204 @@
+//! Events logged by state-changing methods, following NEP-297.
+//!
+//! Each event is a single log line starting with `EVENT_JSON:`, followed by a
+//! JSON object with `standard`, `version`, `event`, and `data`. Indexers can
+//! follow what teller does from these logs, without interpreting receipts.
+//!
+//! Amounts are yocto NEAR, except for fungible token payments. `hot` is the
+//! balance left after the action in the allowance that the action is charged
+//! against. That is the allowance of a trusted recipient for payments to one,
+//! the signing key's own allowance for keys that have one, and the general
+//! hot allowance otherwise.
+
+use crate::recovery::RecoveryProposal;
+use crate::schedule::Schedule;
+use crate::staking::Redelegation;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId, PublicKey};
+
+const STANDARD: &str = "near_teller";
+const VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+#[serde(
+    crate = "near_sdk::serde",
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case"
+)]
+pub(crate) enum Event<'a> {
+    Pay {
+        receiver: &'a AccountId,
+        yocto: U128,
+        hot: U128,
+    },
+    PaymentDelayed {
+        id: u64,
+        receiver: &'a AccountId,
+        yocto: U128,
+        release_at: U64,
+        /// Co-signer approvals needed before release.
+        approvals_required: u32,
+        hot: U128,
+    },
+    PaymentApproved {
+        id: u64,
+        co_signer: &'a AccountId,
+        approvals: u32,
+        approvals_required: u32,
+    },
+    PaymentReleased {
+        id: u64,
+        receiver: &'a AccountId,
+        yocto: U128,
+    },
+    /// A transfer failed and its amount was given back to the allowance.
+    PaymentRefunded {
+        receiver: &'a AccountId,
+        yocto: U128,
+        hot: U128,
+    },
+    PaymentCancelled {
+        id: u64,
+        receiver: &'a AccountId,
+        yocto: U128,
+    },
+    /// Payment with `CONFIG.fungible_tokens[token_index]`, amounts in its smallest unit.
+    PayFt {
+        token_index: usize,
+        token: &'a AccountId,
+        receiver: &'a AccountId,
+        amount: U128,
+        /// yocto NEAR attached to `storage_deposit`, if the receiver was registered.
+        storage_deposit: Option<U128>,
+        hot: U128,
+    },
+    FtPaymentRefunded {
+        token_index: usize,
+        token: &'a AccountId,
+        receiver: &'a AccountId,
+        amount: U128,
+        /// yocto NEAR attached to `storage_deposit`, if the receiver was registered.
+        storage_deposit: Option<U128>,
+        hot: U128,
+    },
+    ScheduleCreated {
+        schedule: &'a Schedule,
+    },
+    ScheduleCancelled {
+        id: u64,
+    },
+    Lock {
+        yocto: U128,
+        hot: U128,
+    },
+    Unlock {
+        yocto: U128,
+        hot: U128,
+    },
+    SetRate {
+        nano_near_per_second: U128,
+        hot: U128,
+    },
+    Stake {
+        pool: usize,
+        staking_pool: &'a AccountId,
+        yocto: U128,
+        hot: U128,
+    },
+    /// `yocto` is `None` for `unstake_all`.
+    Unstake {
+        pool: usize,
+        staking_pool: &'a AccountId,
+        yocto: Option<U128>,
+        hot: U128,
+    },
+    /// `yocto` is `None` for `withdraw_all`.
+    Withdraw {
+        pool: usize,
+        staking_pool: &'a AccountId,
+        yocto: Option<U128>,
+        hot: U128,
+    },
+    /// Withdrawn staking rewards credited to the hot allowance.
+    StakingRewards {
+        staking_pool: &'a AccountId,
+        yocto: U128,
+        hot: U128,
+    },
+    Redelegate {
+        from: usize,
+        to: usize,
+        redelegation: &'a Redelegation,
+    },
+    RedelegationCancelled {
+        redelegation: &'a Redelegation,
+    },
+    RedelegationCompleted {
+        redelegation: &'a Redelegation,
+        yocto: U128,
+    },
+    /// The whole liquid balance was sent to the beneficiary.
+    InheritanceClaimed {
+        beneficiary: &'a AccountId,
+        yocto: U128,
+    },
+    RecoveryProposed {
+        recovery: &'a RecoveryProposal,
+    },
+    /// `executable_at` is set once enough guardians approved.
+    RecoveryApproved {
+        id: u64,
+        guardian: &'a AccountId,
+        executable_at: Option<U64>,
+    },
+    RecoveryCancelled {
+        id: u64,
+    },
+    /// The proposed full access key was added.
+    RecoveryExecuted {
+        id: u64,
+        public_key: &'a PublicKey,
+    },
+    Freeze,
+    Unfreeze,
+    /// Function call access key added, `allowance` is for gas fees.
+    AddAccessKey {
+        public_key: &'a PublicKey,
+        allowance: U128,
+    },
+    DeleteAccessKey {
+        public_key: &'a PublicKey,
+    },
+    AddStakingPool {
+        pool: usize,
+        staking_pool: &'a AccountId,
+    },
+    RemoveStakingPool {
+        pool: usize,
+        staking_pool: &'a AccountId,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Event<'_> {
+    pub(crate) fn emit(&self) {
+        let log = EventLog {
+            standard: STANDARD,
+            version: VERSION,
+            event: self,
+        };
+        let json = serde_json::to_string(&log).expect("events are always serializable");
+        env::log_str(&format!("EVENT_JSON:{json}"));
+    }
+}
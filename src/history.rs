@@ -0,0 +1,105 @@
+//! Ledger of payments and locks, kept in contract storage.
+//!
+//! The aggregate allowance does not tell what the hot balance was used for.
+//! The history keeps one entry per `pay` and `lock`, so that this can be
+//! audited on-chain.
+//!
+//! Storage is bounded by `CONFIG.history_capacity`. Entries are stored in a
+//! ring buffer, once it is full, each new entry overwrites the oldest one.
+//! Entries keep their index, which counts all entries ever recorded, so that
+//! pagination stays stable while old entries are dropped.
+//!
+//! The capacity is fixed when the history is created. Changing it in the
+//! config and migrating does not affect an existing history, since the slot of
+//! each entry depends on it.
+
+use crate::CONFIG;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::Vector;
+use near_sdk::{env, AccountId, Balance};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HistoryEntry {
+    /// Position among all entries ever recorded.
+    pub index: u64,
+    /// Block timestamp (ns) of the operation.
+    pub timestamp: U64,
+    pub kind: HistoryKind,
+    /// Amount in yocto NEAR.
+    pub yocto: U128,
+    /// Receiver of a payment, `None` for locks.
+    pub receiver: Option<AccountId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum HistoryKind {
+    Pay,
+    Lock,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct History {
+    /// Ring buffer, entry `i` is stored at `i % capacity`.
+    entries: Vector<HistoryEntry>,
+    /// Number of entries ever recorded.
+    len: u64,
+    /// `CONFIG.history_capacity` when the history was created.
+    capacity: u32,
+}
+
+impl History {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: Vector::new(b"h"),
+            len: 0,
+            capacity: CONFIG.history_capacity,
+        }
+    }
+
+    /// Number of entries ever recorded, including those no longer stored.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        kind: HistoryKind,
+        yocto: Balance,
+        receiver: Option<AccountId>,
+    ) {
+        let capacity = self.capacity as u64;
+        if capacity == 0 {
+            return;
+        }
+        let entry = HistoryEntry {
+            index: self.len,
+            timestamp: env::block_timestamp().into(),
+            kind,
+            yocto: yocto.into(),
+            receiver,
+        };
+        let slot = (self.len % capacity) as u32;
+        if slot < self.entries.len() {
+            self.entries.set(slot, entry);
+        } else {
+            self.entries.push(entry);
+        }
+        self.len += 1;
+    }
+
+    /// Up to `limit` stored entries, starting at index `from_index`.
+    ///
+    /// Entries before `from_index` that are no longer stored are skipped.
+    pub(crate) fn entries(&self, from_index: u64, limit: u32) -> Vec<&HistoryEntry> {
+        let capacity = self.capacity as u64;
+        let first_stored = self.len - self.entries.len() as u64;
+        (from_index.max(first_stored)..self.len)
+            .take(limit as usize)
+            .map(|i| &self.entries[(i % capacity) as u32])
+            .collect()
+    }
+}
@@ -9,90 +9,1215 @@
 //! to check that any method that changes internal state does have access check
 //! in place.
 
+use crate::allowance::Allowance;
 use crate::error::Error;
-use crate::{Teller, TellerExt};
-use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, GasWeight};
+use crate::events::Event;
+use crate::history::{History, HistoryKind};
+use crate::keys::{KeyTier, HOT_KEY_METHODS};
+use crate::migration::{write_version, VersionedTeller};
+use crate::pending::PendingPayment;
+use crate::recovery::RecoveryProposal;
+use crate::schedule::Schedule;
+use crate::staking::{PoolAccount, Redelegation, StakingAction, StakingRecord};
+use crate::window::SpendingWindow;
+use crate::{trusted_recipient, Teller, TellerExt, CONFIG};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, json};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, GasWeight, PromiseResult, PublicKey};
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Static gas for callbacks into teller, on top of their share of unused gas.
+const CALLBACK_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Most payments in one `pay_batch`. Each payment needs about 25 Tgas for the
+/// transfer and its refund callback, out of the 300 Tgas a transaction can use.
+const MAX_BATCH_SIZE: usize = 10;
+
+/// Selects the allowance that a spending operation is charged against.
+///
+/// Payments remember their budget, so that a refund goes back to the same
+/// allowance.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Budget {
+    /// The general hot allowance.
+    Hot,
+    /// The allowance of `CONFIG.trusted_recipients[i]`.
+    Trusted(usize),
+    /// The allowance of a function call access key in `Teller::key_tiers`.
+    Key(PublicKey),
+}
+
 #[near_bindgen]
 impl Teller {
-    /// Called after deployment, if redeployed, delete account first.
+    /// Called after the first deployment, call `migrate` after redeploying.
     #[init]
     pub fn init() -> Self {
-        Self {
-            t0: env::block_timestamp(),
-            locked: 0,
+        write_version();
+        Self::new()
+    }
+
+    /// Called after redeploying, upgrades the stored state to the current layout.
+    ///
+    /// Also applies changes to the config, see `reconcile_config`. Stake from
+    /// before the first version that tracks it is marked `untracked` in
+    /// `staking_summary`, it can still be unstaked and withdrawn.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        match Self::migrate_impl() {
+            Ok(teller) => teller,
+            Err(e) => e.panic(),
         }
     }
 }
 
 impl Teller {
+    pub(crate) fn migrate_impl() -> Result<Self> {
+        Self::check_predecessor()?;
+        let teller = VersionedTeller::read().migrate();
+        write_version();
+        Ok(teller)
+    }
+
+    /// State as after `init`.
+    pub(crate) fn new() -> Self {
+        Self {
+            allowance: Allowance::new(),
+            trusted_allowances: CONFIG
+                .trusted_recipients
+                .iter()
+                .map(|_| Allowance::new())
+                .collect(),
+            ft_allowances: CONFIG
+                .fungible_tokens
+                .iter()
+                .map(|_| Allowance::new())
+                .collect(),
+            nano_near_per_second: CONFIG.nano_near_per_second,
+            key_tiers: CONFIG
+                .hot_keys
+                .iter()
+                .map(|hot_key| {
+                    let Ok(public_key) = hot_key.public_key.parse() else {
+                        env::panic_str("invalid pre-installed public key");
+                    };
+                    let tier = KeyTier::new(hot_key.nano_near_per_second, hot_key.max_hot);
+                    (public_key, tier)
+                })
+                .collect(),
+            spending_window: SpendingWindow::default(),
+            pending_payments: vec![],
+            next_payment_id: 0,
+            staking_pools: CONFIG
+                .staking_pools
+                .iter()
+                .map(|pool| {
+                    let Ok(staking_pool) = pool.parse() else {
+                        env::panic_str("invalid pre-installed account");
+                    };
+                    staking_pool
+                })
+                .collect(),
+            staking: Default::default(),
+            redelegation: None,
+            history: History::new(),
+            schedules: vec![],
+            next_schedule_id: 0,
+            frozen: false,
+            last_heartbeat: env::block_timestamp(),
+            recoveries: vec![],
+            next_recovery_id: 0,
+        }
+    }
+
     pub(crate) fn pay_impl(&mut self, yocto: Balance, receiver: &AccountId) -> Result<()> {
-        Self::check_access()?;
-        self.try_lock(yocto)?;
+        self.check_access()?;
+        let budget = self.budget_for(receiver);
+        self.try_lock(&budget, yocto)?;
+        self.send_payment(budget, yocto, receiver);
+        Ok(())
+    }
 
-        let index: u64 = env::promise_batch_create(receiver);
+    /// Pay several receivers, either all of them or none.
+    ///
+    /// The sum per allowance and the total are checked before anything is
+    /// charged, then each payment is charged and sent like with `pay`.
+    pub(crate) fn pay_batch_impl(&mut self, payments: &[(AccountId, Balance)]) -> Result<()> {
+        self.check_access()?;
+        if payments.is_empty() {
+            return Err(Error::EmptyBatch);
+        }
+        if payments.len() > MAX_BATCH_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
+        let mut totals: Vec<(Budget, Balance)> = vec![];
+        for (i, (receiver, yocto)) in payments.iter().enumerate() {
+            if payments[..i].iter().any(|(other, _)| other == receiver) {
+                return Err(Error::DuplicateReceiver);
+            }
+            let budget = self.budget_for(receiver);
+            match totals.iter_mut().find(|(b, _)| *b == budget) {
+                Some((_, total)) => *total = total.saturating_add(*yocto),
+                None => totals.push((budget, *yocto)),
+            }
+        }
+        let mut total: Balance = 0;
+        for (budget, yocto) in &totals {
+            if self.available(budget) < *yocto {
+                return Err(Error::NotEnoughHot);
+            }
+            total = total.saturating_add(*yocto);
+        }
+        let max_per_day = CONFIG.max_per_day as u128 * 10u128.pow(24);
+        self.spending_window.check(total, max_per_day)?;
+
+        for (receiver, yocto) in payments {
+            let budget = self.budget_for(receiver);
+            self.try_lock(&budget, *yocto)?;
+            self.send_payment(budget, *yocto, receiver);
+        }
+        Ok(())
+    }
+
+    /// Send or delay a payment that has already been charged to `budget`.
+    fn send_payment(&mut self, budget: Budget, yocto: Balance, receiver: &AccountId) {
+        self.history
+            .record(HistoryKind::Pay, yocto, Some(receiver.clone()));
+        let hot = self.available(&budget).into();
+
+        let delayed = yocto > CONFIG.delayed_payment_threshold as u128 * 10u128.pow(24);
+        let approvals_required = Self::approvals_required(yocto);
+        if delayed || approvals_required > 0 {
+            let delay = if delayed {
+                CONFIG.payment_delay_seconds * 1_000_000_000
+            } else {
+                0
+            };
+            let payment = PendingPayment {
+                id: self.next_payment_id,
+                receiver: receiver.clone(),
+                yocto: yocto.into(),
+                release_at: (env::block_timestamp() + delay).into(),
+                budget,
+                approvals: vec![],
+                approvals_required,
+            };
+            Event::PaymentDelayed {
+                id: payment.id,
+                receiver,
+                yocto: payment.yocto,
+                release_at: payment.release_at,
+                approvals_required,
+                hot,
+            }
+            .emit();
+            self.pending_payments.push(payment);
+            self.next_payment_id += 1;
+        } else {
+            Self::transfer(yocto, receiver, &budget);
+            Event::Pay {
+                receiver,
+                yocto: yocto.into(),
+                hot,
+            }
+            .emit();
+        }
+    }
+
+    pub(crate) fn release_impl(&mut self, id: u64) -> Result<()> {
+        self.check_access()?;
+        self.check_not_frozen()?;
+        let i = self.pending_payment_index(id)?;
+        let payment = &self.pending_payments[i];
+        if env::block_timestamp() < payment.release_at.0 {
+            return Err(Error::PaymentStillDelayed);
+        }
+        if payment.approvals.len() < payment.approvals_required as usize {
+            return Err(Error::PaymentNotApproved);
+        }
+        let payment = self.pending_payments.remove(i);
+        Self::transfer(payment.yocto.0, &payment.receiver, &payment.budget);
+        Event::PaymentReleased {
+            id,
+            receiver: &payment.receiver,
+            yocto: payment.yocto,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Approval of a pending payment by a co-signer.
+    ///
+    /// This is the only method that co-signers can call. The payment still
+    /// has to be released by the owner.
+    pub(crate) fn approve_payment_impl(&mut self, id: u64) -> Result<()> {
+        let co_signer = Self::check_co_signer()?;
+        let i = self.pending_payment_index(id)?;
+        let payment = &mut self.pending_payments[i];
+        if payment.approvals.contains(&co_signer) {
+            return Err(Error::AlreadyApproved);
+        }
+        payment.approvals.push(co_signer);
+        Event::PaymentApproved {
+            id,
+            co_signer: payment.approvals.last().expect("just pushed"),
+            approvals: payment.approvals.len() as u32,
+            approvals_required: payment.approvals_required,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Callback after every transfer of `pay`, `pay_batch`, and `release`.
+    ///
+    /// A transfer to a named account that does not exist fails and the tokens
+    /// are refunded to teller. They are given back to the allowance they were
+    /// charged against. The daily limit is not restored.
+    pub(crate) fn on_pay_impl(
+        &mut self,
+        receiver: AccountId,
+        yocto: Balance,
+        budget: Budget,
+    ) -> Result<()> {
+        self.check_access()?;
+        if let PromiseResult::Successful(_) = Self::callback_result()? {
+            return Ok(());
+        }
+        self.allowance_mut(&budget).unlock(yocto);
+        Event::PaymentRefunded {
+            receiver: &receiver,
+            yocto: yocto.into(),
+            hot: self.available(&budget).into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Pay with fungible_tokens[i], charged to the token's own allowance.
+    ///
+    /// `ft_transfer` requires exactly 1 yocto NEAR attached, which is paid by
+    /// teller without charging the NEAR allowance.
+    ///
+    /// With `register`, the receiver is registered with `storage_deposit`
+    /// first, in the same receipt as the transfer. The deposit is charged to
+    /// the NEAR allowance of the signing key. It is charged in full even if the token contract
+    /// refunds parts of it, for example because the receiver was registered already.
+    pub(crate) fn pay_ft_impl(
+        &mut self,
+        i: usize,
+        amount: Balance,
+        receiver: &AccountId,
+        register: bool,
+    ) -> Result<()> {
+        self.check_access()?;
+        self.check_not_frozen()?;
+        // token allowances are shared, they would bypass the limit of the key
+        let budget = self.signer_budget();
+        if let Budget::Key(_) = budget {
+            return Err(Error::KeyTierCannotPayTokens);
+        }
+        let token = CONFIG.fungible_tokens.get(i).ok_or(Error::UnknownToken)?;
+        if self.available_ft(i)? < amount {
+            return Err(Error::NotEnoughHot);
+        }
+        let storage_deposit = register.then_some(token.storage_deposit);
+        if let Some(yocto) = storage_deposit {
+            self.try_lock(&budget, yocto)?;
+        }
+        self.ft_allowances[i].try_lock(amount, token.units_per_second, token.max_hot)?;
+        let token_account = Self::ft_account(i);
+
+        let index: u64 = env::promise_batch_create(&token_account);
+        if let Some(yocto) = storage_deposit {
+            let args = json!({ "account_id": receiver, "registration_only": true }).to_string();
+            env::promise_batch_action_function_call_weight(
+                index,
+                "storage_deposit",
+                args.as_bytes(),
+                yocto,
+                Gas(0),
+                GasWeight(1),
+            );
+        }
+        let args = json!({ "receiver_id": receiver, "amount": U128(amount) }).to_string();
+        env::promise_batch_action_function_call_weight(
+            index,
+            "ft_transfer",
+            args.as_bytes(),
+            1,
+            Gas(0),
+            GasWeight(1),
+        );
+        let args = json!({
+            "token_index": i,
+            "receiver": receiver,
+            "amount": U128(amount),
+            "storage_deposit": storage_deposit.map(U128),
+            "budget": budget,
+        });
+        let callback = env::promise_batch_then(index, &env::current_account_id());
+        env::promise_batch_action_function_call_weight(
+            callback,
+            "on_ft_pay",
+            args.to_string().as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+        Event::PayFt {
+            token_index: i,
+            token: &token_account,
+            receiver,
+            amount: amount.into(),
+            storage_deposit: storage_deposit.map(U128),
+            hot: self.available_ft(i)?.into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Callback after `ft_transfer`, gives the amount back to the allowance if
+    /// the transfer failed. For example, the receiver might not be registered.
+    /// A storage deposit made in the same receipt is refunded by the runtime
+    /// and given back to the NEAR allowance it was charged to.
+    pub(crate) fn on_ft_pay_impl(
+        &mut self,
+        i: usize,
+        receiver: AccountId,
+        amount: Balance,
+        storage_deposit: Option<Balance>,
+        budget: Budget,
+    ) -> Result<()> {
+        self.check_access()?;
+        if let PromiseResult::Successful(_) = Self::callback_result()? {
+            return Ok(());
+        }
+        self.ft_allowances
+            .get_mut(i)
+            .ok_or(Error::UnknownToken)?
+            .unlock(amount);
+        if let Some(yocto) = storage_deposit {
+            self.allowance_mut(&budget).unlock(yocto);
+        }
+        Event::FtPaymentRefunded {
+            token_index: i,
+            token: &Self::ft_account(i),
+            receiver: &receiver,
+            amount: amount.into(),
+            storage_deposit: storage_deposit.map(U128),
+            hot: self.available_ft(i)?.into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    pub(crate) fn create_schedule_impl(
+        &mut self,
+        receiver: AccountId,
+        yocto: Balance,
+        period_seconds: u64,
+        count: u32,
+    ) -> Result<u64> {
+        self.check_full_access()?;
+        if period_seconds == 0 || count == 0 {
+            return Err(Error::InvalidSchedule);
+        }
+        let schedule = Schedule {
+            id: self.next_schedule_id,
+            receiver,
+            yocto: yocto.into(),
+            period_seconds,
+            remaining: count,
+            next_at: env::block_timestamp().into(),
+        };
+        Event::ScheduleCreated {
+            schedule: &schedule,
+        }
+        .emit();
+        self.schedules.push(schedule);
+        self.next_schedule_id += 1;
+        Ok(self.next_schedule_id - 1)
+    }
+
+    /// Send one payment for each schedule that is due, up to `MAX_BATCH_SIZE`.
+    ///
+    /// There are no access checks, anyone can trigger payments once they are
+    /// due. A payment that exceeds the hot balance or the daily limit is
+    /// skipped and stays due. Schedules that fell behind catch up by one
+    /// payment per call.
+    pub(crate) fn execute_due_impl(&mut self) -> Result<()> {
+        self.check_not_frozen()?;
+        let now = env::block_timestamp();
+        let mut sent = 0;
+        let mut i = 0;
+        while i < self.schedules.len() && sent < MAX_BATCH_SIZE {
+            let schedule = &self.schedules[i];
+            if schedule.next_at.0 > now {
+                i += 1;
+                continue;
+            }
+            let receiver = schedule.receiver.clone();
+            let yocto = schedule.yocto.0;
+            // not `budget_for`, which would depend on who calls `execute_due`
+            let budget = trusted_recipient(&receiver).map_or(Budget::Hot, Budget::Trusted);
+            if self.try_lock(&budget, yocto).is_err() {
+                i += 1;
+                continue;
+            }
+            self.send_payment(budget, yocto, &receiver);
+            sent += 1;
+
+            let schedule = &mut self.schedules[i];
+            schedule.remaining -= 1;
+            schedule.next_at.0 += schedule.period_seconds * 1_000_000_000;
+            if schedule.remaining == 0 {
+                self.schedules.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn cancel_schedule_impl(&mut self, id: u64) -> Result<()> {
+        self.check_access()?;
+        let i = self
+            .schedules
+            .iter()
+            .position(|schedule| schedule.id == id)
+            .ok_or(Error::UnknownSchedule)?;
+        self.schedules.remove(i);
+        Event::ScheduleCancelled { id }.emit();
+        Ok(())
+    }
+
+    pub(crate) fn cancel_payment_impl(&mut self, id: u64) -> Result<()> {
+        self.check_full_access()?;
+        let i = self.pending_payment_index(id)?;
+        let payment = self.pending_payments.remove(i);
+        Event::PaymentCancelled {
+            id,
+            receiver: &payment.receiver,
+            yocto: payment.yocto,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Any call by the owner is a heartbeat, this one just does nothing else.
+    pub(crate) fn heartbeat_impl(&mut self) -> Result<()> {
+        self.check_access()
+    }
+
+    /// Send everything not needed for storage to the beneficiary.
+    ///
+    /// This bypasses all allowances, it is meant for when the owner has lost
+    /// access or passed away. Tokens at staking pools are not claimed. It can
+    /// be called repeatedly, for example to claim tokens arriving later.
+    pub(crate) fn claim_inheritance_impl(&mut self) -> Result<()> {
+        let inheritance = CONFIG.inheritance.as_ref().ok_or(Error::NoInheritance)?;
+        let beneficiary: AccountId = inheritance
+            .beneficiary
+            .parse()
+            .map_err(|_| Error::NoInheritance)?;
+        if env::predecessor_account_id() != beneficiary {
+            return Err(Error::NotBeneficiary);
+        }
+        match self.inheritance_due_at() {
+            Some(due) if due <= env::block_timestamp() => {}
+            _ => return Err(Error::InheritanceNotDue),
+        }
+        let storage_cost = env::storage_usage() as u128 * env::storage_byte_cost();
+        let yocto = env::account_balance().saturating_sub(storage_cost);
+        let index = env::promise_batch_create(&beneficiary);
         env::promise_batch_action_transfer(index, yocto);
+        Event::InheritanceClaimed {
+            beneficiary: &beneficiary,
+            yocto: yocto.into(),
+        }
+        .emit();
+        Ok(())
+    }
 
+    pub(crate) fn inheritance_due_at(&self) -> Option<u64> {
+        let inheritance = CONFIG.inheritance.as_ref()?;
+        Some(self.last_heartbeat + inheritance.inactivity_seconds * 1_000_000_000)
+    }
+
+    pub(crate) fn propose_recovery_impl(&mut self, public_key: PublicKey) -> Result<u64> {
+        let guardian = Self::check_guardian()?;
+        let id = self.next_recovery_id;
+        self.next_recovery_id += 1;
+        self.recoveries.push(RecoveryProposal {
+            id,
+            public_key,
+            approvals: vec![],
+            executable_at: None,
+        });
+        let recovery = self.recoveries.last().expect("just pushed");
+        Event::RecoveryProposed { recovery }.emit();
+        self.approve(self.recoveries.len() - 1, guardian);
+        Ok(id)
+    }
+
+    pub(crate) fn approve_recovery_impl(&mut self, id: u64) -> Result<()> {
+        let guardian = Self::check_guardian()?;
+        let i = self.recovery_index(id)?;
+        if self.recoveries[i].approvals.contains(&guardian) {
+            return Err(Error::AlreadyApproved);
+        }
+        self.approve(i, guardian);
+        Ok(())
+    }
+
+    /// Add the new full access key. Anyone can do this once it is due.
+    pub(crate) fn execute_recovery_impl(&mut self, id: u64) -> Result<()> {
+        let i = self.recovery_index(id)?;
+        match self.recoveries[i].executable_at {
+            Some(at) if at.0 <= env::block_timestamp() => {}
+            _ => return Err(Error::RecoveryNotReady),
+        }
+        let recovery = self.recoveries.remove(i);
+        let index = env::promise_batch_create(&env::current_account_id());
+        env::promise_batch_action_add_key_with_full_access(index, &recovery.public_key, 0);
+        Event::RecoveryExecuted {
+            id,
+            public_key: &recovery.public_key,
+        }
+        .emit();
+        Ok(())
+    }
+
+    pub(crate) fn cancel_recovery_impl(&mut self, id: u64) -> Result<()> {
+        self.check_full_access()?;
+        let i = self.recovery_index(id)?;
+        self.recoveries.remove(i);
+        Event::RecoveryCancelled { id }.emit();
+        Ok(())
+    }
+
+    /// Add an approval, which starts the delay once there are enough.
+    fn approve(&mut self, i: usize, guardian: AccountId) {
+        let Some(recovery) = CONFIG.recovery.as_ref() else {
+            return;
+        };
+        let proposal = &mut self.recoveries[i];
+        proposal.approvals.push(guardian);
+        if proposal.executable_at.is_none()
+            && proposal.approvals.len() >= recovery.threshold as usize
+        {
+            let delay = recovery.delay_seconds * 1_000_000_000;
+            proposal.executable_at = Some((env::block_timestamp() + delay).into());
+        }
+        Event::RecoveryApproved {
+            id: proposal.id,
+            guardian: proposal.approvals.last().expect("just pushed"),
+            executable_at: proposal.executable_at,
+        }
+        .emit();
+    }
+
+    fn recovery_index(&self, id: u64) -> Result<usize> {
+        self.recoveries
+            .iter()
+            .position(|recovery| recovery.id == id)
+            .ok_or(Error::UnknownRecovery)
+    }
+
+    /// Stop all spending, for example when a hot key may have leaked.
+    ///
+    /// Any key can freeze, only the full access key can unfreeze. Staking is
+    /// still possible, it does not move tokens out of the account.
+    pub(crate) fn freeze_impl(&mut self) -> Result<()> {
+        self.check_access()?;
+        self.frozen = true;
+        Event::Freeze.emit();
+        Ok(())
+    }
+
+    pub(crate) fn unfreeze_impl(&mut self) -> Result<()> {
+        self.check_full_access()?;
+        self.frozen = false;
+        Event::Unfreeze.emit();
+        Ok(())
+    }
+
+    pub(crate) fn add_access_key_impl(
+        &mut self,
+        public_key: &PublicKey,
+        allowance: Balance,
+    ) -> Result<()> {
+        self.check_full_access()?;
+        // the host treats 0 as no limit on gas fees at all
+        if allowance == 0 {
+            return Err(Error::ZeroKeyAllowance);
+        }
+        let contract = env::current_account_id();
+        let index = env::promise_batch_create(&contract);
+        env::promise_batch_action_add_key_with_function_call(
+            index,
+            public_key,
+            0,
+            allowance,
+            &contract,
+            HOT_KEY_METHODS,
+        );
+        Event::AddAccessKey {
+            public_key,
+            allowance: allowance.into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    pub(crate) fn delete_access_key_impl(&mut self, public_key: &PublicKey) -> Result<()> {
+        self.check_full_access()?;
+        Self::delete_key(public_key);
+        Ok(())
+    }
+
+    /// Delete the key that signed the transaction.
+    pub(crate) fn revoke_self_impl(&mut self) -> Result<()> {
+        self.check_access()?;
+        Self::delete_key(&env::signer_account_pk());
         Ok(())
     }
 
     pub(crate) fn lock_impl(&mut self, n: Balance) -> Result<()> {
-        Self::check_access()?;
-        self.try_lock(n)?;
+        self.check_access()?;
+        let budget = self.signer_budget();
+        self.try_lock(&budget, n)?;
+        self.history.record(HistoryKind::Lock, n, None);
+        Event::Lock {
+            yocto: n.into(),
+            hot: self.available(&budget).into(),
+        }
+        .emit();
         Ok(())
     }
 
-    pub(crate) fn stake_impl(&mut self, yocto: Balance, staking_pool: &AccountId) -> Result<()> {
-        Self::check_access()?;
-        let index: u64 = env::promise_batch_create(&staking_pool);
+    pub(crate) fn unlock_impl(&mut self, yocto: Balance) -> Result<()> {
+        self.check_full_access()?;
+        self.allowance.unlock(yocto);
+        Event::Unlock {
+            yocto: yocto.into(),
+            hot: self.available(&Budget::Hot).into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Change the rate of the hot allowance, keeping what accrued so far.
+    ///
+    /// Lowering the rate is always safe, so any key can do it. Raising it
+    /// requires the full access key.
+    pub(crate) fn set_rate_impl(&mut self, nano_near_per_second: u128) -> Result<()> {
+        if nano_near_per_second > self.nano_near_per_second {
+            self.check_full_access()?;
+        } else {
+            self.check_access()?;
+        }
+        let per_second = self.yocto_per_second(&Budget::Hot);
+        let max_hot = self.max_hot(&Budget::Hot);
+        self.allowance.checkpoint(per_second, max_hot);
+        self.nano_near_per_second = nano_near_per_second;
+        Event::SetRate {
+            nano_near_per_second: nano_near_per_second.into(),
+            hot: self.available(&Budget::Hot).into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    pub(crate) fn stake_impl(&mut self, yocto: Balance, i: usize) -> Result<()> {
+        self.check_access()?;
+        let staking_pool = self.staking_pool(i)?;
+        Self::deposit_and_stake(staking_pool, yocto);
+        Event::Stake {
+            pool: i,
+            staking_pool,
+            yocto: yocto.into(),
+            hot: self.available(&Budget::Hot).into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Unstake `yocto` or, if `None`, everything staked with staking pool[i].
+    pub(crate) fn unstake_impl(&mut self, i: usize, yocto: Option<Balance>) -> Result<()> {
+        self.check_access()?;
+        let staking_pool = self.staking_pool(i)?;
+        let (method, args) = match yocto {
+            Some(yocto) => ("unstake", json!({ "amount": U128(yocto) }).to_string()),
+            None => ("unstake_all", String::new()),
+        };
+        let index: u64 = env::promise_batch_create(staking_pool);
+        let attached_balance = 0;
         env::promise_batch_action_function_call_weight(
             index,
-            "deposit_and_stake",
-            &[],
-            yocto,
+            method,
+            args.as_bytes(),
+            attached_balance,
             Gas(0),
             GasWeight(1),
         );
+        Self::staking_callback(index, staking_pool, StakingAction::Unstake);
+        Event::Unstake {
+            pool: i,
+            staking_pool,
+            yocto: yocto.map(U128),
+            hot: self.available(&Budget::Hot).into(),
+        }
+        .emit();
         Ok(())
     }
 
-    pub(crate) fn unstake_impl(&mut self, staking_pool: &AccountId) -> Result<()> {
-        Self::check_access()?;
-        let index: u64 = env::promise_batch_create(&staking_pool);
+    /// Withdraw `yocto` or, if `None`, everything unstaked from staking pool[i].
+    pub(crate) fn withdraw_impl(&mut self, i: usize, yocto: Option<Balance>) -> Result<()> {
+        self.check_access()?;
+        let staking_pool = self.staking_pool(i)?;
+        let (method, args) = match yocto {
+            Some(yocto) => ("withdraw", json!({ "amount": U128(yocto) }).to_string()),
+            None => ("withdraw_all", String::new()),
+        };
+        let index: u64 = env::promise_batch_create(staking_pool);
         let attached_balance = 0;
         env::promise_batch_action_function_call_weight(
             index,
-            "unstake_all",
-            &[],
+            method,
+            args.as_bytes(),
             attached_balance,
             Gas(0),
             GasWeight(1),
         );
+        Self::staking_callback(index, staking_pool, StakingAction::Withdraw);
+        Event::Withdraw {
+            pool: i,
+            staking_pool,
+            yocto: yocto.map(U128),
+            hot: self.available(&Budget::Hot).into(),
+        }
+        .emit();
         Ok(())
     }
 
-    pub(crate) fn withdraw_impl(&mut self, staking_pool: &AccountId) -> Result<()> {
-        Self::check_access()?;
+    /// Start moving everything at staking pool[from] to staking pool[to].
+    ///
+    /// This unstakes everything at the first pool right away. The rest happens
+    /// in `progress_redelegation`, once the unstaked balance can be withdrawn.
+    pub(crate) fn redelegate_impl(&mut self, from: usize, to: usize) -> Result<()> {
+        self.check_access()?;
+        if self.redelegation.is_some() {
+            return Err(Error::RedelegationInProgress);
+        }
+        if from == to {
+            return Err(Error::RedelegationToSamePool);
+        }
+        let redelegation = self.redelegation.insert(Redelegation {
+            from: self.staking_pool(from)?.clone(),
+            to: self.staking_pool(to)?.clone(),
+        });
+        Event::Redelegate {
+            from,
+            to,
+            redelegation,
+        }
+        .emit();
+        self.unstake_impl(from, None)
+    }
+
+    pub(crate) fn cancel_redelegation_impl(&mut self) -> Result<()> {
+        self.check_access()?;
+        let redelegation = self.redelegation.take().ok_or(Error::NoRedelegation)?;
+        Event::RedelegationCancelled {
+            redelegation: &redelegation,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Check if the ongoing redelegation can be completed and do it, if so.
+    ///
+    /// Anyone can call this, since it only ever moves tokens between the
+    /// staking pools chosen with `redelegate`.
+    pub(crate) fn progress_redelegation_impl(&mut self) -> Result<()> {
+        let redelegation = self.redelegation.as_ref().ok_or(Error::NoRedelegation)?;
+        Self::query_staking_pool_account(&redelegation.from, "on_redelegation_account", json!({}));
+        Ok(())
+    }
+
+    pub(crate) fn on_redelegation_account_impl(&mut self) -> Result<()> {
+        self.check_access()?;
+        let account = Self::staking_pool_account_result()?;
+        let redelegation = self.redelegation.as_ref().ok_or(Error::NoRedelegation)?;
+        if !self.staking_pools.contains(&redelegation.to) {
+            return Err(Error::UnknownStakingPool);
+        }
+        let unstaked = account.unstaked_balance.0;
+        if unstaked == 0 {
+            return Err(Error::NothingToRedelegate);
+        }
+        if !account.can_withdraw {
+            return Err(Error::StakingPoolWithdrawalNotReady);
+        }
+
+        let index: u64 = env::promise_batch_create(&redelegation.from);
+        let args = json!({ "amount": U128(unstaked) }).to_string();
+        let attached_balance = 0;
+        env::promise_batch_action_function_call_weight(
+            index,
+            "withdraw",
+            args.as_bytes(),
+            attached_balance,
+            Gas(0),
+            GasWeight(1),
+        );
+        let callback_index = env::promise_batch_then(index, &env::current_account_id());
+        let args = json!({ "yocto": U128(unstaked) }).to_string();
+        env::promise_batch_action_function_call_weight(
+            callback_index,
+            "on_redelegation_withdrawn",
+            args.as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+        Ok(())
+    }
+
+    /// Stake what has been withdrawn for the redelegation at the new pool.
+    ///
+    /// If the withdrawal failed, the redelegation stays as it is and can be
+    /// progressed again.
+    pub(crate) fn on_redelegation_withdrawn_impl(&mut self, yocto: Balance) -> Result<()> {
+        self.check_access()?;
+        if !matches!(Self::callback_result()?, PromiseResult::Successful(_)) {
+            return Ok(());
+        }
+        let redelegation = self.redelegation.take().ok_or(Error::NoRedelegation)?;
+        let args = json!({
+            "staking_pool": redelegation.from,
+            "action": StakingAction::Redelegate(U128(yocto)),
+        });
+        Self::query_staking_pool_account(&redelegation.from, "on_staking_pool_account", args);
+        Self::deposit_and_stake(&redelegation.to, yocto);
+        Event::RedelegationCompleted {
+            redelegation: &redelegation,
+            yocto: yocto.into(),
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Callback after any call to a staking pool.
+    ///
+    /// Failed calls are ignored. After successful calls, ask the pool for the
+    /// new balances, which are stored in `on_staking_pool_account`.
+    pub(crate) fn on_staking_action_impl(
+        &mut self,
+        staking_pool: AccountId,
+        action: StakingAction,
+    ) -> Result<()> {
+        self.check_access()?;
+        if !matches!(Self::callback_result()?, PromiseResult::Successful(_)) {
+            return Ok(());
+        }
+        let record = self
+            .staking
+            .entry(staking_pool.clone())
+            .or_insert_with(|| StakingRecord::first_used_for(action));
+        if let StakingAction::Stake(yocto) = action {
+            record.deposited.0 += yocto.0;
+            record.principal.0 += yocto.0;
+        }
+        let args = json!({ "staking_pool": staking_pool, "action": action });
+        Self::query_staking_pool_account(&staking_pool, "on_staking_pool_account", args);
+        Ok(())
+    }
+
+    pub(crate) fn on_staking_pool_account_impl(
+        &mut self,
+        staking_pool: AccountId,
+        action: StakingAction,
+    ) -> Result<()> {
+        self.check_access()?;
+        let account = Self::staking_pool_account_result()?;
+
+        let record = self
+            .staking
+            .entry(staking_pool.clone())
+            .or_insert_with(|| StakingRecord::first_used_for(action));
+        let before = record.unstaked_balance.0;
+        let after = account.unstaked_balance.0;
+        let (unstaked, withdrawn) = match action {
+            StakingAction::Stake(_) => (0, 0),
+            StakingAction::Unstake => (after.saturating_sub(before), 0),
+            StakingAction::Withdraw => (0, before.saturating_sub(after)),
+            StakingAction::Harvest { withdraw, unstake } => (unstake.0, withdraw.0),
+            StakingAction::Redelegate(yocto) => (0, yocto.0),
+        };
+        record.update_balances(&account);
+        record.unstaked.0 += unstaked;
+        let rewards = record.realize(withdrawn);
+        // redelegated rewards are staked again at the new pool
+        let redelegated = matches!(action, StakingAction::Redelegate(_));
+        if CONFIG.staking_rewards_to_hot && !redelegated && rewards > 0 {
+            self.allowance.unlock(rewards);
+            Event::StakingRewards {
+                staking_pool: &staking_pool,
+                yocto: rewards.into(),
+                hot: self.available(&Budget::Hot).into(),
+            }
+            .emit();
+        }
+        Ok(())
+    }
+
+    /// Move rewards out of staking pool[i], so that they are realized.
+    ///
+    /// This withdraws what is unstaked and ready for withdrawal and unstakes
+    /// the remaining rewards. When unstaked tokens are not ready, yet, it does
+    /// nothing, since unstaking more would delay their withdrawal again.
+    pub(crate) fn harvest_impl(&mut self, i: usize) -> Result<()> {
+        self.check_access()?;
+        let staking_pool = self.staking_pool(i)?;
+        let args = json!({ "staking_pool": staking_pool });
+        Self::query_staking_pool_account(staking_pool, "on_harvest_account", args);
+        Ok(())
+    }
+
+    pub(crate) fn on_harvest_account_impl(&mut self, staking_pool: AccountId) -> Result<()> {
+        self.check_access()?;
+        let account = Self::staking_pool_account_result()?;
+        let record = self
+            .staking
+            .entry(staking_pool.clone())
+            .or_insert_with(StakingRecord::untracked);
+        record.update_balances(&account);
+
+        let unstaked = account.unstaked_balance.0;
+        if unstaked > 0 && !account.can_withdraw {
+            return Err(Error::StakingPoolWithdrawalNotReady);
+        }
+        let rewards = record.unrealized_rewards();
+        let unstake = rewards.saturating_sub(unstaked);
+        if unstaked == 0 && unstake == 0 {
+            return Ok(());
+        }
+
         let index: u64 = env::promise_batch_create(&staking_pool);
         let attached_balance = 0;
+        if unstaked > 0 {
+            let args = json!({ "amount": U128(unstaked) }).to_string();
+            env::promise_batch_action_function_call_weight(
+                index,
+                "withdraw",
+                args.as_bytes(),
+                attached_balance,
+                Gas(0),
+                GasWeight(1),
+            );
+        }
+        if unstake > 0 {
+            let args = json!({ "amount": U128(unstake) }).to_string();
+            env::promise_batch_action_function_call_weight(
+                index,
+                "unstake",
+                args.as_bytes(),
+                attached_balance,
+                Gas(0),
+                GasWeight(1),
+            );
+        }
+        let action = StakingAction::Harvest {
+            withdraw: U128(unstaked),
+            unstake: U128(unstake),
+        };
+        Self::staking_callback(index, &staking_pool, action);
+        Ok(())
+    }
+
+    pub(crate) fn add_staking_pool_impl(&mut self, staking_pool: AccountId) -> Result<()> {
+        self.check_full_access()?;
+        self.insert_staking_pool(staking_pool)
+    }
+
+    pub(crate) fn remove_staking_pool_impl(&mut self, i: usize) -> Result<()> {
+        self.check_full_access()?;
+        self.staking_pool(i)?;
+        let staking_pool = self.staking_pools.remove(i);
+        Event::RemoveStakingPool {
+            pool: i,
+            staking_pool: &staking_pool,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Add a staking pool deployed by `CONFIG.staking_pool_factory`, without
+    /// the full access key.
+    ///
+    /// Being a subaccount of the factory is not enough, the factory could have
+    /// created the account for anyone to deploy arbitrary code on. Only the
+    /// whitelist contract used by the factory knows which of them really are
+    /// staking pool contracts. Thus, the pool is only added in the callback.
+    pub(crate) fn add_factory_staking_pool_impl(&mut self, staking_pool: AccountId) -> Result<()> {
+        self.check_access()?;
+        let Some(name) = staking_pool
+            .as_str()
+            .strip_suffix(CONFIG.staking_pool_factory)
+            .and_then(|prefix| prefix.strip_suffix('.'))
+        else {
+            return Err(Error::NotAFactoryStakingPool);
+        };
+        if name.contains('.') {
+            return Err(Error::NotAFactoryStakingPool);
+        }
+        if self.staking_pools.contains(&staking_pool) {
+            return Err(Error::StakingPoolAlreadyListed);
+        }
+
+        let Ok(whitelist) = CONFIG.staking_pool_whitelist.parse() else {
+            env::panic_str("invalid pre-installed account");
+        };
+        let args = json!({ "staking_pool_account_id": staking_pool }).to_string();
+        let index: u64 = env::promise_batch_create(&whitelist);
         env::promise_batch_action_function_call_weight(
             index,
-            "withdraw_all",
+            "is_whitelisted",
+            args.as_bytes(),
+            0,
+            Gas(0),
+            GasWeight(1),
+        );
+        let args = json!({ "staking_pool": staking_pool }).to_string();
+        let callback = env::promise_batch_then(index, &env::current_account_id());
+        env::promise_batch_action_function_call_weight(
+            callback,
+            "on_staking_pool_whitelisted",
+            args.as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+        Ok(())
+    }
+
+    pub(crate) fn on_staking_pool_whitelisted_impl(
+        &mut self,
+        staking_pool: AccountId,
+    ) -> Result<()> {
+        self.check_access()?;
+        let PromiseResult::Successful(value) = Self::callback_result()? else {
+            return Err(Error::StakingPoolNotWhitelisted);
+        };
+        match serde_json::from_slice(&value) {
+            Ok(true) => self.insert_staking_pool(staking_pool),
+            _ => Err(Error::StakingPoolNotWhitelisted),
+        }
+    }
+
+    /// Schedule `on_staking_action` after the staking pool call at `index`.
+    fn staking_callback(index: u64, staking_pool: &AccountId, action: StakingAction) {
+        let args = json!({ "staking_pool": staking_pool, "action": action }).to_string();
+        let callback = env::promise_batch_then(index, &env::current_account_id());
+        env::promise_batch_action_function_call_weight(
+            callback,
+            "on_staking_action",
+            args.as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+    }
+
+    /// Call `deposit_and_stake` on a staking pool, followed by the usual callback.
+    fn deposit_and_stake(staking_pool: &AccountId, yocto: Balance) {
+        let index: u64 = env::promise_batch_create(staking_pool);
+        env::promise_batch_action_function_call_weight(
+            index,
+            "deposit_and_stake",
             &[],
-            attached_balance,
+            yocto,
             Gas(0),
             GasWeight(1),
         );
+        Self::staking_callback(index, staking_pool, StakingAction::Stake(U128(yocto)));
+    }
+
+    /// Call `get_account` for teller on a staking pool and pass the result to
+    /// `callback` on teller, together with `args`.
+    fn query_staking_pool_account(
+        staking_pool: &AccountId,
+        callback: &str,
+        args: serde_json::Value,
+    ) {
+        let get_account_args = json!({ "account_id": env::current_account_id() }).to_string();
+        let index: u64 = env::promise_batch_create(staking_pool);
+        env::promise_batch_action_function_call_weight(
+            index,
+            "get_account",
+            get_account_args.as_bytes(),
+            0,
+            Gas(0),
+            GasWeight(1),
+        );
+        let callback_index = env::promise_batch_then(index, &env::current_account_id());
+        env::promise_batch_action_function_call_weight(
+            callback_index,
+            callback,
+            args.to_string().as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+    }
+
+    /// Read the result of `query_staking_pool_account` in the callback.
+    fn staking_pool_account_result() -> Result<PoolAccount> {
+        let PromiseResult::Successful(value) = Self::callback_result()? else {
+            return Err(Error::StakingPoolAccountUnavailable);
+        };
+        serde_json::from_slice(&value).map_err(|_| Error::StakingPoolAccountUnavailable)
+    }
+
+    fn staking_pool(&self, i: usize) -> Result<&AccountId> {
+        self.staking_pools.get(i).ok_or(Error::UnknownStakingPool)
+    }
+
+    fn insert_staking_pool(&mut self, staking_pool: AccountId) -> Result<()> {
+        if self.staking_pools.contains(&staking_pool) {
+            return Err(Error::StakingPoolAlreadyListed);
+        }
+        Event::AddStakingPool {
+            pool: self.staking_pools.len(),
+            staking_pool: &staking_pool,
+        }
+        .emit();
+        self.staking_pools.push(staking_pool);
         Ok(())
     }
 
     /// Only allow functions to be called directly, not via cross function call.
     ///
+    /// Calls signed by a key of the account also count as heartbeat, which
+    /// delays `claim_inheritance`.
+    fn check_access(&mut self) -> Result<()> {
+        Self::check_predecessor()?;
+        if env::signer_account_id() == env::current_account_id() {
+            self.last_heartbeat = env::block_timestamp();
+        }
+        Ok(())
+    }
+
+    /// Only allow calls by the account itself, not by other accounts.
+    ///
     /// This is very important to check, as otherwise anyone could call into
     /// teller's methods without any access permission checks!
-    fn check_access() -> Result<()> {
+    fn check_predecessor() -> Result<()> {
         if env::current_account_id() == env::predecessor_account_id() {
             Ok(())
         } else {
@@ -100,12 +1225,201 @@ impl Teller {
         }
     }
 
-    fn try_lock(&mut self, yocto: Balance) -> Result<()> {
-        if self.hot() < yocto {
-            Err(Error::NotEnoughHot)
+    /// Result of the promise that a callback was scheduled on.
+    ///
+    /// Callbacks are called by the contract itself, hence a function call
+    /// access key could call them directly, too. `#[private]` does not prevent
+    /// that. But a direct call has no promise result, which is checked here.
+    fn callback_result() -> Result<PromiseResult> {
+        if env::promise_results_count() != 1 {
+            return Err(Error::NotACallback);
+        }
+        Ok(env::promise_result(0))
+    }
+
+    /// Only allow functions to be called with the full access key.
+    ///
+    /// Function call access keys cannot attach deposits to their calls. Hence,
+    /// if exactly 1 yocto Near is attached, the call must have been signed by a
+    /// full access key. Use this for admin methods that a stolen function call
+    /// access key must not be able to call.
+    fn check_full_access(&mut self) -> Result<()> {
+        self.check_access()?;
+        if env::attached_deposit() == 1 {
+            Ok(())
+        } else {
+            Err(Error::FullAccessKeyRequired)
+        }
+    }
+
+    /// Spending methods must fail while frozen, see `freeze_impl`.
+    fn check_not_frozen(&self) -> Result<()> {
+        if self.frozen {
+            Err(Error::Frozen)
         } else {
-            self.locked += yocto;
             Ok(())
         }
     }
+
+    /// The calling guardian, if the predecessor is one.
+    fn check_guardian() -> Result<AccountId> {
+        let guardian = env::predecessor_account_id();
+        let recovery = CONFIG.recovery.as_ref().ok_or(Error::NotGuardian)?;
+        if recovery.guardians.contains(&guardian.as_str()) {
+            Ok(guardian)
+        } else {
+            Err(Error::NotGuardian)
+        }
+    }
+
+    /// The calling co-signer, if the predecessor is one.
+    ///
+    /// Co-signers are foreign accounts. Only `approve_payment` accepts them,
+    /// all other methods keep using `check_access`.
+    fn check_co_signer() -> Result<AccountId> {
+        let co_signer = env::predecessor_account_id();
+        let co_signers = CONFIG.co_signers.as_ref().ok_or(Error::NotCoSigner)?;
+        if co_signers.accounts.contains(&co_signer.as_str()) {
+            Ok(co_signer)
+        } else {
+            Err(Error::NotCoSigner)
+        }
+    }
+
+    /// Co-signer approvals needed for a payment of `yocto`.
+    fn approvals_required(yocto: Balance) -> u32 {
+        match CONFIG.co_signers.as_ref() {
+            Some(co_signers) if yocto > co_signers.payment_threshold as u128 * 10u128.pow(24) => {
+                co_signers.approvals_required
+            }
+            _ => 0,
+        }
+    }
+
+    fn delete_key(public_key: &PublicKey) {
+        let index = env::promise_batch_create(&env::current_account_id());
+        env::promise_batch_action_delete_key(index, public_key);
+        Event::DeleteAccessKey { public_key }.emit();
+    }
+
+    /// Send tokens charged to `budget`, followed by `on_pay`.
+    fn transfer(yocto: Balance, receiver: &AccountId, budget: &Budget) {
+        let index: u64 = env::promise_batch_create(receiver);
+        env::promise_batch_action_transfer(index, yocto);
+        let args = json!({ "receiver": receiver, "yocto": U128(yocto), "budget": budget });
+        let args = args.to_string();
+        let callback = env::promise_batch_then(index, &env::current_account_id());
+        env::promise_batch_action_function_call_weight(
+            callback,
+            "on_pay",
+            args.as_bytes(),
+            0,
+            CALLBACK_GAS,
+            GasWeight(1),
+        );
+    }
+
+    fn pending_payment_index(&self, id: u64) -> Result<usize> {
+        self.pending_payments
+            .iter()
+            .position(|payment| payment.id == id)
+            .ok_or(Error::UnknownPayment)
+    }
+
+    /// Budget for paying `receiver` in the current transaction.
+    ///
+    /// Trusted recipients have their own allowance, no matter who pays them.
+    /// Otherwise, the budget depends on the key that signed the transaction.
+    pub(crate) fn budget_for(&self, receiver: &AccountId) -> Budget {
+        match trusted_recipient(receiver) {
+            Some(i) => Budget::Trusted(i),
+            None => self.signer_budget(),
+        }
+    }
+
+    /// The allowance of the key that signed the current transaction, if it
+    /// has its own, otherwise the general hot allowance.
+    fn signer_budget(&self) -> Budget {
+        let public_key = env::signer_account_pk();
+        if env::signer_account_id() == env::current_account_id()
+            && self.key_tiers.contains_key(&public_key)
+        {
+            Budget::Key(public_key)
+        } else {
+            Budget::Hot
+        }
+    }
+
+    pub(crate) fn nano_near_per_second(&self, budget: &Budget) -> u128 {
+        match budget {
+            Budget::Hot => self.nano_near_per_second,
+            Budget::Trusted(i) => CONFIG.trusted_recipients[*i].nano_near_per_second,
+            Budget::Key(public_key) => self.key_tiers[public_key].nano_near_per_second,
+        }
+    }
+
+    /// Ceiling in yocto Near.
+    fn max_hot(&self, budget: &Budget) -> Balance {
+        let max_hot = match budget {
+            Budget::Hot => CONFIG.max_hot,
+            Budget::Trusted(i) => CONFIG.trusted_recipients[*i].max_hot,
+            Budget::Key(public_key) => self.key_tiers[public_key].max_hot,
+        };
+        max_hot as u128 * 10u128.pow(24)
+    }
+
+    /// Available balance of fungible_tokens[i] in the smallest unit of the token.
+    pub(crate) fn available_ft(&self, i: usize) -> Result<Balance> {
+        let token = CONFIG.fungible_tokens.get(i).ok_or(Error::UnknownToken)?;
+        Ok(self.ft_allowances[i].available(token.units_per_second, token.max_hot))
+    }
+
+    fn ft_account(i: usize) -> AccountId {
+        let Ok(account_id) = CONFIG.fungible_tokens[i].account_id.parse() else {
+            env::panic_str("invalid pre-installed account");
+        };
+        account_id
+    }
+
+    fn yocto_per_second(&self, budget: &Budget) -> u128 {
+        // nano = e-9, yocto = e-24
+        self.nano_near_per_second(budget) * 10u128.pow(15)
+    }
+
+    /// Available balance in yocto Near.
+    pub(crate) fn available(&self, budget: &Budget) -> Balance {
+        let allowance = match budget {
+            Budget::Hot => &self.allowance,
+            Budget::Trusted(i) => &self.trusted_allowances[*i],
+            Budget::Key(public_key) => &self.key_tiers[public_key].allowance,
+        };
+        allowance.available(self.yocto_per_second(budget), self.max_hot(budget))
+    }
+
+    /// Charge `yocto` to a budget, if not frozen and both the budget and the daily limit allow it.
+    fn try_lock(&mut self, budget: &Budget, yocto: Balance) -> Result<()> {
+        self.check_not_frozen()?;
+        let max_per_day = CONFIG.max_per_day as u128 * 10u128.pow(24);
+        self.spending_window.check(yocto, max_per_day)?;
+        let per_second = self.yocto_per_second(budget);
+        let max_hot = self.max_hot(budget);
+        self.allowance_mut(budget)
+            .try_lock(yocto, per_second, max_hot)?;
+        self.spending_window.record(yocto);
+        Ok(())
+    }
+
+    fn allowance_mut(&mut self, budget: &Budget) -> &mut Allowance {
+        match budget {
+            Budget::Hot => &mut self.allowance,
+            Budget::Trusted(i) => &mut self.trusted_allowances[*i],
+            Budget::Key(public_key) => {
+                &mut self
+                    .key_tiers
+                    .get_mut(public_key)
+                    .expect("key tier must exist")
+                    .allowance
+            }
+        }
+    }
 }
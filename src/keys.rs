@@ -0,0 +1,47 @@
+//! Function call access keys with their own allowance.
+//!
+//! By default, all function call access keys share the general hot allowance.
+//! A key can instead get its own allowance, with its own rate and ceiling, for
+//! example a generous one for a server and a small one for a phone wallet.
+//! Spending signed by such a key is charged only to its own allowance. Such a
+//! key cannot pay fungible tokens, their allowances are shared by all keys.
+//!
+//! Keys can also be added and deleted through teller itself. Such keys may
+//! only call the methods in `HOT_KEY_METHODS` on the contract.
+
+use crate::allowance::Allowance;
+use crate::Near;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::Balance;
+
+/// Methods that keys added by `add_access_key` can call, comma separated.
+///
+/// Callbacks and full access methods are left out, a function call access key
+/// could not successfully call them anyway. `set_rate` is included, function
+/// call access keys can lower the rate.
+pub(crate) const HOT_KEY_METHODS: &str = "pay,pay_yocto,pay_batch,pay_ft,release,\
+lock,lock_yocto,set_rate,stake,stake_yocto,unstake,unstake_amount,unstake_yocto,\
+withdraw,withdraw_amount,withdraw_yocto,harvest,redelegate,progress_redelegation,\
+cancel_redelegation,add_factory_staking_pool,execute_due,cancel_schedule,freeze,\
+heartbeat,revoke_self";
+
+/// Gas allowance of added keys if none is given, same as the NEAR CLI default.
+pub(crate) const DEFAULT_KEY_ALLOWANCE: Balance = 250_000_000_000_000_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct KeyTier {
+    pub allowance: Allowance,
+    pub nano_near_per_second: u128,
+    /// Ceiling in whole Near, accrual stops once it is reached.
+    pub max_hot: Near,
+}
+
+impl KeyTier {
+    pub(crate) fn new(nano_near_per_second: u128, max_hot: Near) -> Self {
+        Self {
+            allowance: Allowance::new(),
+            nano_near_per_second,
+            max_hot,
+        }
+    }
+}
@@ -1,47 +1,218 @@
 //! A token guarding contract for an account with a cold full access key and hot function call access keys.
 //!
-//! The features are simple and few by design. Configuration changes are only possible by redeploying.
+//! The features are simple and few by design. Configuration changes are only possible by redeploying,
+//! except for the rate of the hot allowance and the list of staking pools.
 //!
 //! Possible actions are:
 //!
-//! 1. Pay: Send `arg.N` tokens to `arg.account`.
+//! 1. Pay: Send `arg.N` tokens to `arg.account`. Above `CONFIG.delayed_payment_threshold`, only after a delay.
 //! 2. Lock: Forgo `arg.N` tokens that can no longer be retrieved by 1.
-//! 3. Stake: Call `deposit_and_stake` on `CONFIG.staking_pools[arg.staking_pool]` and attach `arg.N` tokens.
-//! 4. Unstake: Call `unstake_all` on `CONFIG.staking_pools[arg.staking_pool]`.
-//! 5. Unstake: Call `withdraw_all` on `CONFIG.staking_pools[arg.staking_pool]`.
+//! 3. Stake: Call `deposit_and_stake` on `self.staking_pools[arg.staking_pool]` and attach `arg.N` tokens.
+//! 4. Unstake: Call `unstake_all` or `unstake` on `self.staking_pools[arg.staking_pool]`.
+//! 5. Unstake: Call `withdraw_all` or `withdraw` on `self.staking_pools[arg.staking_pool]`.
 //!
 //! Pay and lock are limited by how many tokens are unlocked for hot wallet access.
+//! Payments to one of `CONFIG.trusted_recipients` are instead limited by a
+//! separate allowance kept for that recipient. On top of that, all pay and lock
+//! operations combined are limited to `CONFIG.max_per_day` within 24 hours.
 //! Staking is unlimited. (Besides the external limit of actual tokens in the account.)
+//! Staking rewards can optionally be added to the hot allowance once withdrawn.
 //!
 //! Rationale:
 //!
-//! - No dynamic staking: Calling a method with the name `deposit_and_stake` on an arbitrary account makes it possible to retrieve all tokens with hot key.
-//! - No dynamic rate change: Necessary allowance computation makes code more complicated.
+//! - No arbitrary staking: Calling a method with the name `deposit_and_stake` on an arbitrary account makes it possible to retrieve all tokens with hot key.
+//!   Hence, the hot key can only add staking pools confirmed by the whitelist of the official staking pool factory.
+//! - Dynamic rate change only for the hot allowance: Trusted recipients are meant to be set and forget.
 
+mod allowance;
 mod error;
+mod events;
+mod history;
 mod implementation;
+mod keys;
+mod migration;
+mod pending;
+mod recovery;
+mod schedule;
+mod staking;
 #[cfg(test)]
 mod unit_tests;
+mod window;
 
+use allowance::Allowance;
+use history::{History, HistoryEntry};
+use implementation::Budget;
+use keys::{KeyTier, DEFAULT_KEY_ALLOWANCE};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::{env, near_bindgen, AccountId, Balance};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{near_bindgen, AccountId, Balance, PublicKey};
+use pending::PendingPayment;
+use recovery::RecoveryProposal;
+use schedule::Schedule;
+use staking::{Redelegation, StakingAction, StakingRecord};
+use std::collections::BTreeMap;
+use window::SpendingWindow;
 
 type Near = u32;
 
 struct Config {
     nano_near_per_second: u128,
-    staking_pools: [&'static str; 10],
+    /// Ceiling of `hot()` in whole Near, accrual stops once it is reached.
+    max_hot: Near,
+    /// Most whole Near that can be paid or locked within 24 hours, across all allowances.
+    max_per_day: Near,
+    /// Payments above this many whole Near are delayed by `payment_delay_seconds`.
+    delayed_payment_threshold: Near,
+    payment_delay_seconds: u64,
+    /// Staking pools available right after deployment.
+    staking_pools: &'static [&'static str],
+    /// Factory account, direct subaccounts of it can be added as staking pool with a hot key.
+    staking_pool_factory: &'static str,
+    /// Whitelist contract used by `staking_pool_factory`.
+    staking_pool_whitelist: &'static str,
+    /// Whether withdrawn staking rewards are added to `hot()`.
+    staking_rewards_to_hot: bool,
+    /// Number of payments and locks kept in `history()`, older ones are dropped.
+    history_capacity: u32,
+    trusted_recipients: &'static [TrustedRecipient],
+    /// NEP-141 tokens that can be paid with `pay_ft`, each with its own allowance.
+    fungible_tokens: &'static [FungibleToken],
+    /// Function call access keys with their own allowance.
+    hot_keys: &'static [HotKey],
+    /// Who can claim the balance after the owner stopped calling `heartbeat`.
+    inheritance: Option<Inheritance>,
+    /// Guardians that can add a new full access key.
+    recovery: Option<Recovery>,
+    /// Accounts that must approve large payments.
+    co_signers: Option<CoSigners>,
 }
 
+/// A receiver of payments that has its own allowance, independent of the
+/// allowance for all other receivers.
+struct TrustedRecipient {
+    account_id: &'static str,
+    nano_near_per_second: u128,
+    /// Ceiling in whole Near, accrual stops once it is reached.
+    max_hot: Near,
+}
+
+/// A NEP-141 fungible token contract and the allowance for paying with it.
+///
+/// Amounts are in the smallest unit of the token, for example 1 USDC is
+/// 1_000_000 units.
+struct FungibleToken {
+    account_id: &'static str,
+    units_per_second: u128,
+    /// Ceiling in units, accrual stops once it is reached.
+    max_hot: u128,
+    /// yocto NEAR attached to `storage_deposit` when registering a receiver.
+    storage_deposit: Balance,
+}
+
+/// A function call access key that spends from its own allowance instead of
+/// the general hot allowance.
+struct HotKey {
+    /// For example "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp".
+    public_key: &'static str,
+    nano_near_per_second: u128,
+    /// Ceiling in whole Near, accrual stops once it is reached.
+    max_hot: Near,
+}
+
+/// A beneficiary that receives the balance if the owner has not called
+/// `heartbeat` for `inactivity_seconds`.
+struct Inheritance {
+    beneficiary: &'static str,
+    inactivity_seconds: u64,
+}
+
+/// Guardians of which `threshold` must approve a new full access key, which
+/// is added `delay_seconds` after the last approval.
+struct Recovery {
+    guardians: &'static [&'static str],
+    threshold: u32,
+    delay_seconds: u64,
+}
+
+/// Accounts of which `approvals_required` must approve payments above
+/// `payment_threshold` whole Near, before they can be released.
+struct CoSigners {
+    accounts: &'static [&'static str],
+    approvals_required: u32,
+    payment_threshold: Near,
+}
+
+#[cfg(not(any(test, feature = "sandbox")))]
 const CONFIG: Config = include!("config.ron");
 
+/// `config.ron` with features enabled that are disabled by default, for unit
+/// tests and for the WASM used by sandbox tests.
+#[cfg(any(test, feature = "sandbox"))]
+const CONFIG: Config = Config {
+    staking_rewards_to_hot: true,
+    trusted_recipients: &[TrustedRecipient {
+        account_id: "jakmeier.testnet",
+        nano_near_per_second: 10_000,
+        max_hot: 1_000,
+    }],
+    inheritance: Some(Inheritance {
+        // subaccount of the sandbox root account
+        beneficiary: "heir.test.near",
+        // short enough for sandbox tests to reach with `fast_forward`
+        inactivity_seconds: 60 * 60,
+    }),
+    recovery: Some(Recovery {
+        guardians: &["alice.test.near", "bob.test.near", "carol.test.near"],
+        threshold: 2,
+        delay_seconds: 24 * 60 * 60,
+    }),
+    co_signers: Some(CoSigners {
+        accounts: &["dave.test.near", "erin.test.near", "frank.test.near"],
+        approvals_required: 2,
+        payment_threshold: 10_000,
+    }),
+    ..include!("config.ron")
+};
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, near_sdk::PanicOnDefault)]
 pub struct Teller {
-    /// Initial timestamp (ns) from which the allowance is computed from.
-    t0: u64,
-    /// yocto NEAR either retrieved or forgone.
-    locked: u128,
+    /// Hot allowance for `lock` and for paying any untrusted receiver.
+    allowance: Allowance,
+    /// Current rate of `allowance`, starts at `CONFIG.nano_near_per_second`.
+    nano_near_per_second: u128,
+    /// Allowances of keys that don't use the general hot allowance.
+    key_tiers: BTreeMap<PublicKey, KeyTier>,
+    /// One allowance per entry in `CONFIG.trusted_recipients`.
+    trusted_allowances: Vec<Allowance>,
+    /// One allowance per entry in `CONFIG.fungible_tokens`.
+    ft_allowances: Vec<Allowance>,
+    /// Recent spending from all allowances, to enforce `CONFIG.max_per_day`.
+    spending_window: SpendingWindow,
+    /// Delayed payments that have not been released or cancelled, yet.
+    pending_payments: Vec<PendingPayment>,
+    /// Id of the next delayed payment.
+    next_payment_id: u64,
+    /// Staking pools that `stake` and friends can be used with.
+    staking_pools: Vec<AccountId>,
+    /// What has been done with each staking pool ever used, even if no longer listed.
+    staking: BTreeMap<AccountId, StakingRecord>,
+    /// Ongoing move of stake between two staking pools.
+    redelegation: Option<Redelegation>,
+    /// Recent payments and locks.
+    history: History,
+    /// Recurring payments that have payments left.
+    schedules: Vec<Schedule>,
+    /// Id of the next schedule.
+    next_schedule_id: u64,
+    /// Set by `freeze`, all spending fails until `unfreeze`.
+    frozen: bool,
+    /// Timestamp (ns) of the last `heartbeat`, or of `init`.
+    last_heartbeat: u64,
+    /// Proposed full access keys that have not been executed or cancelled, yet.
+    recoveries: Vec<RecoveryProposal>,
+    /// Id of the next recovery proposal.
+    next_recovery_id: u64,
 }
 
 // Public API of the contract.
@@ -72,6 +243,106 @@ impl Teller {
         }
     }
 
+    /// Send Near tokens to several accounts at once, amounts in yocto Near.
+    ///
+    /// Either all payments are made or none, each receiver may appear only once.
+    pub fn pay_batch(&mut self, payments: Vec<(AccountId, U128)>) {
+        let payments: Vec<_> = payments
+            .into_iter()
+            .map(|(receiver, yocto)| (receiver, yocto.0))
+            .collect();
+        if let Err(e) = self.pay_batch_impl(&payments) {
+            e.panic()
+        }
+    }
+
+    /// Send fungible_tokens[i] to an account, amount in the smallest unit of the token.
+    ///
+    /// The receiver must be registered with the token contract, unless
+    /// `register` is set. Then the storage deposit for it is paid from the hot
+    /// balance in Near. Keys with their own allowance cannot pay tokens.
+    pub fn pay_ft(
+        &mut self,
+        token_index: u32,
+        amount: U128,
+        receiver: AccountId,
+        register: Option<bool>,
+    ) {
+        let register = register.unwrap_or(false);
+        if let Err(e) = self.pay_ft_impl(token_index as usize, amount.0, &receiver, register) {
+            e.panic()
+        }
+    }
+
+    /// Callback of `pay_ft`.
+    #[private]
+    pub fn on_ft_pay(
+        &mut self,
+        token_index: u32,
+        receiver: AccountId,
+        amount: U128,
+        storage_deposit: Option<U128>,
+        budget: Budget,
+    ) {
+        let storage_deposit = storage_deposit.map(|yocto| yocto.0);
+        if let Err(e) = self.on_ft_pay_impl(
+            token_index as usize,
+            receiver,
+            amount.0,
+            storage_deposit,
+            budget,
+        ) {
+            e.panic()
+        }
+    }
+
+    /// Send all payments of schedules that are due, as far as the hot balance
+    /// allows. Anyone can call this.
+    pub fn execute_due(&mut self) {
+        if let Err(e) = self.execute_due_impl() {
+            e.panic()
+        }
+    }
+
+    /// Recurring payments with payments left.
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    /// Stop a recurring payment. Payments already made are not affected.
+    pub fn cancel_schedule(&mut self, id: u64) {
+        if let Err(e) = self.cancel_schedule_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// Callback of the transfer in `pay`, `pay_batch`, and `release`.
+    #[private]
+    pub fn on_pay(&mut self, receiver: AccountId, yocto: U128, budget: Budget) {
+        if let Err(e) = self.on_pay_impl(receiver, yocto.0, budget) {
+            e.panic()
+        }
+    }
+
+    /// Execute a delayed payment, once its delay has passed and co-signers approved it.
+    pub fn release(&mut self, id: u64) {
+        if let Err(e) = self.release_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// Approve a pending payment. Must be called by a co-signer.
+    pub fn approve_payment(&mut self, id: u64) {
+        if let Err(e) = self.approve_payment_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// All delayed payments that have not been released or cancelled, yet.
+    pub fn pending_payments(&self) -> &[PendingPayment] {
+        &self.pending_payments
+    }
+
     /// Make Near tokens unavailable for retrieval from hot wallet. Only whole Near values.
     pub fn lock(&mut self, n: Near) {
         let yocto = n as u128 * 10u128.pow(24);
@@ -88,60 +359,422 @@ impl Teller {
         }
     }
 
+    /// Show that the owner is still around, which delays `claim_inheritance`.
+    pub fn heartbeat(&mut self) {
+        if let Err(e) = self.heartbeat_impl() {
+            e.panic()
+        }
+    }
+
+    /// Timestamp (ns) from which on the beneficiary can claim the balance, if one is configured.
+    pub fn inheritance_due(&self) -> Option<U64> {
+        self.inheritance_due_at().map(U64)
+    }
+
+    /// Send the balance to the beneficiary, if the owner has been inactive for long enough.
+    ///
+    /// Must be called by the beneficiary.
+    pub fn claim_inheritance(&mut self) {
+        if let Err(e) = self.claim_inheritance_impl() {
+            e.panic()
+        }
+    }
+
+    /// Propose a new full access key. Must be called by a guardian.
+    pub fn propose_recovery(&mut self, new_public_key: PublicKey) -> u64 {
+        match self.propose_recovery_impl(new_public_key) {
+            Ok(id) => id,
+            Err(e) => e.panic(),
+        }
+    }
+
+    /// Approve a proposed full access key. Must be called by a guardian.
+    pub fn approve_recovery(&mut self, id: u64) {
+        if let Err(e) = self.approve_recovery_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// Add the proposed full access key, once approved and the delay has passed.
+    pub fn execute_recovery(&mut self, id: u64) {
+        if let Err(e) = self.execute_recovery_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// List all proposed full access keys.
+    pub fn recoveries(&self) -> &[RecoveryProposal] {
+        &self.recoveries
+    }
+
+    /// Stop all spending until `unfreeze` is called with the full access key.
+    pub fn freeze(&mut self) {
+        if let Err(e) = self.freeze_impl() {
+            e.panic()
+        }
+    }
+
+    /// Whether spending is stopped by `freeze`.
+    pub fn frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Delete the access key that signed this call, for example if it might be compromised.
+    ///
+    /// Only call this with a function call access key! With a full access key,
+    /// it deletes that key.
+    pub fn revoke_self(&mut self) {
+        if let Err(e) = self.revoke_self_impl() {
+            e.panic()
+        }
+    }
+
     /// Available balance in yocto Near.
     pub fn hot(&self) -> Balance {
-        let ns = env::block_timestamp() - self.t0;
-        // nano = e-9, yocto = e-24
-        // ns * nNEAR/s = n^2NEAR = NEAR * e-18
-        // need to multiply with e+6 to return in yocto
-        let available_ever = ns as u128 * CONFIG.nano_near_per_second * 10u128.pow(6);
-        available_ever - self.locked
+        self.available(&Budget::Hot)
+    }
+
+    /// Available balance of fungible_tokens[i], in the smallest unit of the token.
+    pub fn hot_ft(&self, token_index: u32) -> Balance {
+        match self.available_ft(token_index as usize) {
+            Ok(units) => units,
+            Err(e) => e.panic(),
+        }
+    }
+
+    /// Rate at which the hot balance currently grows, in nano Near per second.
+    pub fn rate(&self) -> u128 {
+        self.nano_near_per_second(&Budget::Hot)
+    }
+
+    /// Change the rate at which the hot balance grows, in nano Near per second.
+    /// Raising the rate requires the full access key and 1 yocto Near attached.
+    #[payable]
+    pub fn set_rate(&mut self, nano_near_per_second: String) {
+        let nano_near_per_second: u128 = nano_near_per_second
+            .parse()
+            .expect("could not parse input rate");
+        if let Err(e) = self.set_rate_impl(nano_near_per_second) {
+            e.panic()
+        }
+    }
+
+    /// Available balance in yocto Near for payments to the given account.
+    pub fn hot_for(&self, a: AccountId) -> Balance {
+        let budget = trusted_recipient(&a).map_or(Budget::Hot, Budget::Trusted);
+        self.available(&budget)
+    }
+
+    /// Balance in yocto Near currently available for spending signed by the
+    /// given key. This differs from `hot()` only for keys with their own allowance.
+    pub fn hot_for_key(&self, public_key: PublicKey) -> Balance {
+        if self.key_tiers.contains_key(&public_key) {
+            self.available(&Budget::Key(public_key))
+        } else {
+            self.available(&Budget::Hot)
+        }
+    }
+
+    /// Balance in yocto Near that can still be spent within the current 24 hours.
+    pub fn daily_available(&self) -> Balance {
+        let max = CONFIG.max_per_day as u128 * 10u128.pow(24);
+        max.saturating_sub(self.spending_window.spent())
+    }
+
+    /// Number of payments and locks ever recorded in the history.
+    pub fn history_len(&self) -> u64 {
+        self.history.len()
+    }
+
+    /// Up to `limit` payments and locks, starting at index `from_index`.
+    ///
+    /// Only the last `CONFIG.history_capacity` entries, as configured when the
+    /// history was created, are kept, older ones are skipped.
+    pub fn history(&self, from_index: u64, limit: u32) -> Vec<&HistoryEntry> {
+        self.history.entries(from_index, limit)
     }
 
     /// Stake with validator[i].
     pub fn stake(&mut self, i: u32, n: Near) {
-        let staking_pool = select_staking_pool(i as usize);
         let yocto = n as u128 * 10u128.pow(24);
 
-        if let Err(e) = self.stake_impl(yocto, &staking_pool) {
+        if let Err(e) = self.stake_impl(yocto, i as usize) {
             e.panic()
         }
     }
 
     /// Stake with validator[i].Amount is specified in yocto Near.
     pub fn stake_yocto(&mut self, i: u32, yocto: &String) {
-        let staking_pool = select_staking_pool(i as usize);
         let yocto: u128 = yocto.parse().expect("could not parse input yocto");
 
-        if let Err(e) = self.stake_impl(yocto, &staking_pool) {
+        if let Err(e) = self.stake_impl(yocto, i as usize) {
             e.panic()
         }
     }
 
     /// Unstake and withdraw all balance staked with validator[i].
     pub fn unstake(&mut self, i: u32) {
-        let staking_pool = select_staking_pool(i as usize);
+        if let Err(e) = self.unstake_impl(i as usize, None) {
+            e.panic()
+        }
+    }
+
+    /// Unstake part of the balance staked with validator[i]. Only whole Near values.
+    pub fn unstake_amount(&mut self, i: u32, n: Near) {
+        let yocto = n as u128 * 10u128.pow(24);
+
+        if let Err(e) = self.unstake_impl(i as usize, Some(yocto)) {
+            e.panic()
+        }
+    }
 
-        if let Err(e) = self.unstake_impl(&staking_pool) {
+    /// Unstake part of the balance staked with validator[i]. Amount is specified in yocto Near.
+    pub fn unstake_yocto(&mut self, i: u32, yocto: String) {
+        let yocto: u128 = yocto.parse().expect("could not parse input yocto");
+
+        if let Err(e) = self.unstake_impl(i as usize, Some(yocto)) {
             e.panic()
         }
     }
 
     /// Withdraw all balance staked with validator[i].
     pub fn withdraw(&mut self, i: u32) {
-        let staking_pool = select_staking_pool(i as usize);
+        if let Err(e) = self.withdraw_impl(i as usize, None) {
+            e.panic()
+        }
+    }
+
+    /// Withdraw part of the unstaked balance at validator[i]. Only whole Near values.
+    pub fn withdraw_amount(&mut self, i: u32, n: Near) {
+        let yocto = n as u128 * 10u128.pow(24);
+
+        if let Err(e) = self.withdraw_impl(i as usize, Some(yocto)) {
+            e.panic()
+        }
+    }
+
+    /// Withdraw part of the unstaked balance at validator[i]. Amount is specified in yocto Near.
+    pub fn withdraw_yocto(&mut self, i: u32, yocto: String) {
+        let yocto: u128 = yocto.parse().expect("could not parse input yocto");
+
+        if let Err(e) = self.withdraw_impl(i as usize, Some(yocto)) {
+            e.panic()
+        }
+    }
+
+    /// Withdraw rewards from validator[i] that are ready and unstake the
+    /// remaining rewards, so they can be withdrawn by a later call.
+    pub fn harvest(&mut self, i: u32) {
+        if let Err(e) = self.harvest_impl(i as usize) {
+            e.panic()
+        }
+    }
+
+    /// Move all stake from validator[from] to validator[to].
+    ///
+    /// Unstakes everything at validator[from] now. Call `progress_redelegation`
+    /// to finish once the unstaked balance can be withdrawn.
+    pub fn redelegate(&mut self, from: u32, to: u32) {
+        if let Err(e) = self.redelegate_impl(from as usize, to as usize) {
+            e.panic()
+        }
+    }
+
+    /// Withdraw and stake at the new validator, if the unstaked balance of the
+    /// ongoing redelegation can be withdrawn. Anyone can call this.
+    pub fn progress_redelegation(&mut self) {
+        if let Err(e) = self.progress_redelegation_impl() {
+            e.panic()
+        }
+    }
+
+    /// Stop the ongoing redelegation. Unstaked tokens stay at the old validator.
+    pub fn cancel_redelegation(&mut self) {
+        if let Err(e) = self.cancel_redelegation_impl() {
+            e.panic()
+        }
+    }
+
+    /// Ongoing redelegation, if any.
+    pub fn redelegation(&self) -> Option<&Redelegation> {
+        self.redelegation.as_ref()
+    }
+
+    /// Staking pools that can be used with `stake`, `unstake`, and `withdraw`.
+    pub fn staking_pools(&self) -> &[AccountId] {
+        &self.staking_pools
+    }
+
+    /// Amounts moved in and out of each staking pool ever used by teller, and
+    /// their balances as reported by the pool after the last action.
+    pub fn staking_summary(&self) -> &BTreeMap<AccountId, StakingRecord> {
+        &self.staking
+    }
+
+    /// Callback of `stake`, `unstake`, and `withdraw`.
+    #[private]
+    pub fn on_staking_action(&mut self, staking_pool: AccountId, action: StakingAction) {
+        if let Err(e) = self.on_staking_action_impl(staking_pool, action) {
+            e.panic()
+        }
+    }
+
+    /// Callback of `on_staking_action`.
+    #[private]
+    pub fn on_staking_pool_account(&mut self, staking_pool: AccountId, action: StakingAction) {
+        if let Err(e) = self.on_staking_pool_account_impl(staking_pool, action) {
+            e.panic()
+        }
+    }
+
+    /// Callback of `harvest`.
+    #[private]
+    pub fn on_harvest_account(&mut self, staking_pool: AccountId) {
+        if let Err(e) = self.on_harvest_account_impl(staking_pool) {
+            e.panic()
+        }
+    }
+
+    /// Callback of `progress_redelegation`.
+    #[private]
+    pub fn on_redelegation_account(&mut self) {
+        if let Err(e) = self.on_redelegation_account_impl() {
+            e.panic()
+        }
+    }
+
+    /// Callback of `on_redelegation_account`.
+    #[private]
+    pub fn on_redelegation_withdrawn(&mut self, yocto: U128) {
+        if let Err(e) = self.on_redelegation_withdrawn_impl(yocto.0) {
+            e.panic()
+        }
+    }
+
+    /// Add a direct subaccount of the staking pool factory as staking pool, if
+    /// the factory's whitelist confirms it is a staking pool.
+    pub fn add_factory_staking_pool(&mut self, a: AccountId) {
+        if let Err(e) = self.add_factory_staking_pool_impl(a) {
+            e.panic()
+        }
+    }
+
+    /// Callback of `add_factory_staking_pool`.
+    #[private]
+    pub fn on_staking_pool_whitelisted(&mut self, staking_pool: AccountId) {
+        if let Err(e) = self.on_staking_pool_whitelisted_impl(staking_pool) {
+            e.panic()
+        }
+    }
+}
+
+// Admin API of the contract.
+//
+// Same rules as above apply. Additionally, these methods must only be callable
+// with the full access key. To prove that, callers must attach exactly 1 yocto
+// Near, which function call access keys cannot do.
+#[near_bindgen]
+impl Teller {
+    /// Make Near tokens available for retrieval from hot wallet immediately. Only whole Near values.
+    #[payable]
+    pub fn unlock(&mut self, n: Near) {
+        let yocto = n as u128 * 10u128.pow(24);
+        if let Err(e) = self.unlock_impl(yocto) {
+            e.panic()
+        }
+    }
+
+    /// Make Near tokens available for retrieval from hot wallet immediately. Amount is specified in yocto Near.
+    #[payable]
+    pub fn unlock_yocto(&mut self, yocto: String) {
+        let yocto: u128 = yocto.parse().expect("could not parse input yocto");
+        if let Err(e) = self.unlock_impl(yocto) {
+            e.panic()
+        }
+    }
+
+    /// Cancel a delayed payment. The tokens stay locked.
+    #[payable]
+    pub fn cancel_payment(&mut self, id: u64) {
+        if let Err(e) = self.cancel_payment_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// Add any account as staking pool. Make sure it really is one!
+    #[payable]
+    pub fn add_staking_pool(&mut self, a: AccountId) {
+        if let Err(e) = self.add_staking_pool_impl(a) {
+            e.panic()
+        }
+    }
+
+    /// Pay `amount` yocto Near to `receiver` every `period_secs`, `count` times.
+    ///
+    /// The first payment is due immediately. Payments are sent by `execute_due`.
+    #[payable]
+    pub fn create_schedule(
+        &mut self,
+        receiver: AccountId,
+        amount: U128,
+        period_secs: u64,
+        count: u32,
+    ) -> u64 {
+        match self.create_schedule_impl(receiver, amount.0, period_secs, count) {
+            Ok(id) => id,
+            Err(e) => e.panic(),
+        }
+    }
+
+    /// Reject a proposed full access key.
+    #[payable]
+    pub fn cancel_recovery(&mut self, id: u64) {
+        if let Err(e) = self.cancel_recovery_impl(id) {
+            e.panic()
+        }
+    }
+
+    /// Allow spending again after `freeze`.
+    #[payable]
+    pub fn unfreeze(&mut self) {
+        if let Err(e) = self.unfreeze_impl() {
+            e.panic()
+        }
+    }
+
+    /// Add a function call access key that can call teller's hot methods.
+    ///
+    /// `allowance` is in yocto Near and pays for gas fees, 0.25 Near if none is given.
+    /// 0 is rejected, the protocol would make it unlimited.
+    #[payable]
+    pub fn add_access_key(&mut self, public_key: PublicKey, allowance: Option<U128>) {
+        let allowance = allowance.map_or(DEFAULT_KEY_ALLOWANCE, |allowance| allowance.0);
+        if let Err(e) = self.add_access_key_impl(&public_key, allowance) {
+            e.panic()
+        }
+    }
+
+    /// Delete any access key of the account.
+    #[payable]
+    pub fn delete_access_key(&mut self, public_key: PublicKey) {
+        if let Err(e) = self.delete_access_key_impl(&public_key) {
+            e.panic()
+        }
+    }
 
-        if let Err(e) = self.withdraw_impl(&staking_pool) {
+    /// Remove staking pool[i]. This changes the index of all pools after it.
+    #[payable]
+    pub fn remove_staking_pool(&mut self, i: u32) {
+        if let Err(e) = self.remove_staking_pool_impl(i as usize) {
             e.panic()
         }
     }
 }
 
-fn select_staking_pool(i: usize) -> AccountId {
-    // safety: rust will panic on out-of-bound access
-    let staking_pool_str = CONFIG.staking_pools[i];
-    let Ok(staking_pool) = staking_pool_str.parse() else {
-        env::panic_str("invalid pre-installed account");
-    };
-    staking_pool
+/// Index of the account in `CONFIG.trusted_recipients`, if it is listed.
+fn trusted_recipient(account_id: &AccountId) -> Option<usize> {
+    CONFIG
+        .trusted_recipients
+        .iter()
+        .position(|recipient| recipient.account_id == account_id.as_str())
 }
@@ -0,0 +1,130 @@
+//! Upgrading the state of a deployed teller after redeploying.
+//!
+//! Borsh does not tag the layout of a struct, so the version of the stored
+//! `Teller` is written under a separate storage key. State without that key
+//! was written by the first version, which only stored `{t0, locked}`.
+//!
+//! When the layout of `Teller` changes, add the previous layout here as a new
+//! variant of `VersionedTeller`, convert it in `migrate`, and increment
+//! `STATE_VERSION`.
+//!
+//! `migrate` also brings the state in line with a changed `config.ron`, see
+//! `reconcile_config`. Trusted recipients and fungible tokens are referenced
+//! by their index, so they can only be appended to.
+
+use crate::allowance::Allowance;
+use crate::implementation::Budget;
+use crate::staking::StakingRecord;
+use crate::{Teller, CONFIG};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::env;
+
+/// Storage key of the layout version, next to `STATE` of the contract.
+const VERSION_KEY: &[u8] = b"VERSION";
+
+/// Version of the current `Teller` layout.
+pub(crate) const STATE_VERSION: u8 = 1;
+
+/// The first layout, before any of the allowances besides the hot one.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct TellerV0 {
+    /// Initial timestamp (ns) from which the allowance is computed from.
+    pub t0: u64,
+    /// yocto NEAR either retrieved or forgone.
+    pub locked: u128,
+}
+
+/// State in any layout that has been deployed.
+pub(crate) enum VersionedTeller {
+    V0(TellerV0),
+    V1(Box<Teller>),
+}
+
+impl VersionedTeller {
+    /// Read the stored state, in the layout it was written with.
+    pub(crate) fn read() -> Self {
+        let Some(state) = env::storage_read(b"STATE") else {
+            env::panic_str("no state to migrate");
+        };
+        let version = env::storage_read(VERSION_KEY).map_or(0, |version| version[0]);
+        let result = match version {
+            0 => TellerV0::try_from_slice(&state).map(Self::V0),
+            1 => Teller::try_from_slice(&state).map(|teller| Self::V1(Box::new(teller))),
+            _ => env::panic_str("unknown state version"),
+        };
+        result.unwrap_or_else(|_| env::panic_str("state does not match its version"))
+    }
+
+    /// Convert to the current layout.
+    ///
+    /// Everything added after the stored version starts as it would with `init`.
+    /// Stake in the pre-installed pools predates the staking records, so their
+    /// records start untracked.
+    pub(crate) fn migrate(self) -> Teller {
+        let mut teller = match self {
+            Self::V0(v0) => {
+                let mut teller = Teller {
+                    allowance: Allowance::from_parts(v0.t0, v0.locked),
+                    ..Teller::new()
+                };
+                for pool in &teller.staking_pools {
+                    teller
+                        .staking
+                        .insert(pool.clone(), StakingRecord::untracked());
+                }
+                teller
+            }
+            Self::V1(teller) => *teller,
+        };
+        teller.reconcile_config();
+        teller
+    }
+}
+
+impl Teller {
+    /// Adapt the state derived from `CONFIG` after it changed.
+    ///
+    /// Allowances of appended trusted recipients and fungible tokens start as
+    /// with `init`. Hot keys keep what they accrued so far and continue with
+    /// their new rate and ceiling. Removed ones lose their allowance, and their
+    /// pending payments are refunded to the general allowance instead.
+    ///
+    /// The rates of trusted recipients and fungible tokens are not stored, so
+    /// their allowances cannot be adapted to a new rate.
+    fn reconcile_config(&mut self) {
+        if self.trusted_allowances.len() > CONFIG.trusted_recipients.len()
+            || self.ft_allowances.len() > CONFIG.fungible_tokens.len()
+        {
+            env::panic_str("trusted recipients and fungible tokens can only be appended");
+        }
+        self.trusted_allowances
+            .resize_with(CONFIG.trusted_recipients.len(), Allowance::new);
+        self.ft_allowances
+            .resize_with(CONFIG.fungible_tokens.len(), Allowance::new);
+
+        let mut key_tiers = Teller::new().key_tiers;
+        for (public_key, tier) in &mut key_tiers {
+            if let Some(mut old) = self.key_tiers.remove(public_key) {
+                // keep what accrued at the old rate, as `set_rate` does
+                old.allowance.checkpoint(
+                    old.nano_near_per_second * 10u128.pow(15),
+                    old.max_hot as u128 * 10u128.pow(24),
+                );
+                tier.allowance = old.allowance;
+            }
+        }
+        for payment in &mut self.pending_payments {
+            if let Budget::Key(public_key) = &payment.budget {
+                if !key_tiers.contains_key(public_key) {
+                    payment.budget = Budget::Hot;
+                }
+            }
+        }
+        self.key_tiers = key_tiers;
+    }
+}
+
+/// Remember that the state is stored in the current layout.
+pub(crate) fn write_version() {
+    env::storage_write(VERSION_KEY, &[STATE_VERSION]);
+}
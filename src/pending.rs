@@ -0,0 +1,33 @@
+//! Payments that wait in contract state before they are executed.
+//!
+//! A payment above `CONFIG.delayed_payment_threshold` is charged against the
+//! hot allowance right away but the transfer only happens when `release` is
+//! called after `CONFIG.payment_delay_seconds`. Until then, the owner can
+//! cancel it with the full access key, which gives time to react to a stolen
+//! function access key.
+//!
+//! A payment above the threshold of `CONFIG.co_signers` is kept here, too,
+//! until enough co-signers approved it with `approve_payment`.
+
+use crate::implementation::Budget;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::AccountId;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingPayment {
+    pub id: u64,
+    pub receiver: AccountId,
+    /// Amount in yocto NEAR.
+    pub yocto: U128,
+    /// Timestamp (ns) from which on the payment can be released.
+    pub release_at: U64,
+    /// Allowance the payment was charged to.
+    pub budget: Budget,
+    /// Co-signers that approved the payment.
+    pub approvals: Vec<AccountId>,
+    /// Number of co-signer approvals needed before the payment can be released.
+    pub approvals_required: u32,
+}
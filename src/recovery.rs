@@ -0,0 +1,24 @@
+//! Social recovery, in case the full access key is lost.
+//!
+//! Guardians listed in `CONFIG.recovery` can propose a new full access key.
+//! Once enough guardians approved it, the proposal can be executed after a
+//! delay. Until then, the owner can cancel it with the full access key, so
+//! that guardians cannot take over an account whose owner is still around.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::{AccountId, PublicKey};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecoveryProposal {
+    pub id: u64,
+    /// Full access key to be added.
+    pub public_key: PublicKey,
+    /// Guardians that approved, including the one that proposed.
+    pub approvals: Vec<AccountId>,
+    /// Timestamp (ns) from which on it can be executed, set once enough
+    /// guardians approved.
+    pub executable_at: Option<U64>,
+}
@@ -0,0 +1,25 @@
+//! Recurring payments, such as bills or stipends.
+//!
+//! The owner creates schedules with the full access key. Anyone can then call
+//! `execute_due` to send the payments that are due, which are charged to the
+//! hot allowances like any other payment. If the hot balance is not
+//! sufficient, the payment stays due until it is.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::AccountId;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Schedule {
+    pub id: u64,
+    pub receiver: AccountId,
+    /// Amount per payment in yocto NEAR.
+    pub yocto: U128,
+    pub period_seconds: u64,
+    /// Number of payments still to be made.
+    pub remaining: u32,
+    /// Timestamp (ns) from which on the next payment is due.
+    pub next_at: U64,
+}
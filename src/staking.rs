@@ -0,0 +1,161 @@
+//! Book keeping of what teller did with each staking pool.
+//!
+//! Every call to a staking pool is followed by a callback. If the call was
+//! successful, teller queries the pool for its account and stores the balances
+//! reported by the pool. `unstake_all` and `withdraw_all` do not report how
+//! much they moved, so the amounts are derived from how the unstaked balance at
+//! the pool changed. Nothing besides unstaking and withdrawing changes that
+//! balance when teller only uses `deposit_and_stake`.
+//!
+//! Teller also keeps track of the principal in each pool, which is what has
+//! been deposited minus what of it has been withdrawn. Everything else in the
+//! pool are rewards. Withdrawals count as rewards first, as long as there are
+//! any, and can be credited to the hot allowance.
+//!
+//! A pool may also hold stake that teller did not track, for example stake
+//! from before migrating the first version of teller, which did not keep
+//! records, or stake added with the full access key. The principal of such a
+//! pool is unknown, so nothing withdrawn from it counts as rewards. Teller
+//! notices such stake when the first action is not staking, or when a pool that
+//! held nothing reports more than teller deposited.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{AccountId, Balance};
+
+/// yocto NEAR a pool may report beyond the principal due to rounding of shares.
+const ROUNDING_TOLERANCE: Balance = 1_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingRecord {
+    /// yocto NEAR deposited and staked through teller in total.
+    pub deposited: U128,
+    /// yocto NEAR unstaked through teller in total.
+    pub unstaked: U128,
+    /// yocto NEAR withdrawn through teller in total.
+    pub withdrawn: U128,
+    /// yocto NEAR deposited and not yet withdrawn again.
+    pub principal: U128,
+    /// yocto NEAR withdrawn on top of principal in total.
+    pub rewards: U128,
+    /// Staked balance reported by the pool after the last action.
+    pub staked_balance: U128,
+    /// Unstaked balance reported by the pool after the last action.
+    pub unstaked_balance: U128,
+    /// Whether the pool may hold stake that teller did not track.
+    pub untracked: bool,
+}
+
+impl Default for StakingRecord {
+    fn default() -> Self {
+        Self {
+            deposited: U128(0),
+            unstaked: U128(0),
+            withdrawn: U128(0),
+            principal: U128(0),
+            rewards: U128(0),
+            staked_balance: U128(0),
+            unstaked_balance: U128(0),
+            untracked: false,
+        }
+    }
+}
+
+impl StakingRecord {
+    /// Record of a pool with stake of unknown principal.
+    pub(crate) fn untracked() -> Self {
+        Self {
+            untracked: true,
+            ..Default::default()
+        }
+    }
+
+    /// Record of a pool that teller has not used before.
+    ///
+    /// If the first action is not staking, the pool must hold stake that
+    /// teller did not track.
+    pub(crate) fn first_used_for(action: StakingAction) -> Self {
+        match action {
+            StakingAction::Stake(_) => Self::default(),
+            _ => Self::untracked(),
+        }
+    }
+
+    /// Store the balances reported by the pool after an action.
+    ///
+    /// A pool that held nothing before has not earned rewards since, so more
+    /// than the principal means the pool holds stake that teller did not track.
+    pub(crate) fn update_balances(&mut self, account: &PoolAccount) {
+        let was_empty = self.staked_balance.0 == 0 && self.unstaked_balance.0 == 0;
+        self.staked_balance = account.staked_balance;
+        self.unstaked_balance = account.unstaked_balance;
+        let reported = self.staked_balance.0 + self.unstaked_balance.0;
+        if was_empty && reported > self.principal.0 + ROUNDING_TOLERANCE {
+            self.untracked = true;
+        }
+    }
+
+    /// Rewards currently in the pool, according to the last reported balances.
+    pub(crate) fn unrealized_rewards(&self) -> Balance {
+        if self.untracked {
+            return 0;
+        }
+        (self.staked_balance.0 + self.unstaked_balance.0).saturating_sub(self.principal.0)
+    }
+
+    /// Book a withdrawal of `yocto` and return how much of it were rewards.
+    ///
+    /// Call this after storing the balances reported by the pool after the withdrawal.
+    pub(crate) fn realize(&mut self, yocto: Balance) -> Balance {
+        if self.untracked {
+            self.withdrawn.0 += yocto;
+            return 0;
+        }
+        let rewards_before = (self.staked_balance.0 + self.unstaked_balance.0 + yocto)
+            .saturating_sub(self.principal.0);
+        let rewards = yocto.min(rewards_before);
+        self.withdrawn.0 += yocto;
+        self.rewards.0 += rewards;
+        self.principal.0 -= yocto - rewards;
+        rewards
+    }
+}
+
+/// What teller asked a staking pool to do, passed on to callbacks.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StakingAction {
+    /// Deposit and stake the given yocto NEAR.
+    Stake(U128),
+    Unstake,
+    Withdraw,
+    /// Withdraw and then unstake the given yocto NEAR, as done by `harvest`.
+    Harvest {
+        withdraw: U128,
+        unstake: U128,
+    },
+    /// Withdraw the given yocto NEAR to stake it at another pool.
+    Redelegate(U128),
+}
+
+/// Stake that is being moved from one staking pool to another.
+///
+/// Everything at `from` is unstaked when the redelegation starts. Once it can be
+/// withdrawn, `progress_redelegation` withdraws it and stakes it at `to`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Redelegation {
+    pub from: AccountId,
+    pub to: AccountId,
+}
+
+/// Account as returned by `get_account` on a staking pool.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct PoolAccount {
+    pub unstaked_balance: U128,
+    pub staked_balance: U128,
+    pub can_withdraw: bool,
+}
@@ -1,7 +1,20 @@
+use crate::allowance::Allowance;
 use crate::error::Error;
-use crate::{env, AccountId, Balance, Near, Teller};
-use near_sdk::test_utils::VMContextBuilder;
-use near_sdk::{testing_env, VMContext};
+use crate::history::HistoryKind;
+use crate::implementation::Budget;
+use crate::keys::KeyTier;
+use crate::migration::TellerV0;
+use crate::pending::PendingPayment;
+use crate::staking::{StakingAction, StakingRecord};
+use crate::{AccountId, Balance, Near, Teller, CONFIG};
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::mock::VmAction;
+use near_sdk::serde_json::{self, json};
+use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+use near_sdk::{
+    env, testing_env, PromiseResult, PublicKey, RuntimeFeesConfig, VMConfig, VMContext,
+};
 
 #[test]
 fn test_balance() {
@@ -22,6 +35,7 @@ fn test_pay() {
     let tokens = seconds_to_near(giga);
 
     for _ in 0..5 {
+        next_call();
         app.pay(tokens, "max.near".parse().unwrap());
     }
 
@@ -29,6 +43,7 @@ fn test_pay() {
     assert_eq!(expected, app.hot());
 
     for _ in 0..8 {
+        next_call();
         app.pay(tokens, "max.near".parse().unwrap());
     }
     let expected = 0;
@@ -43,6 +58,7 @@ fn test_pay_yocto() {
     let token_string = format!("{tokens}");
 
     for _ in 0..5 {
+        next_call();
         app.pay_yocto(&token_string, "max.near".parse().unwrap());
     }
 
@@ -50,6 +66,7 @@ fn test_pay_yocto() {
     assert_eq!(expected, app.hot());
 
     for _ in 0..8 {
+        next_call();
         app.pay_yocto(&token_string, "max.near".parse().unwrap());
     }
     let expected = 0;
@@ -174,6 +191,1371 @@ fn test_scenario() {
     app.assert_hot(0);
 }
 
+#[test]
+fn test_max_hot() {
+    let mut app = install();
+    let giga = 1_000_000_000; // to avoid Near fractions
+    let max_hot = CONFIG.max_hot as u128 * 10u128.pow(24);
+    let seconds_to_max = (max_hot / seconds_to_yocto(1)) as u64;
+
+    // note: timestamps in ns overflow after ~18 giga seconds
+    fast_forward(10 * giga, seconds_to_max + giga / 10);
+    assert_eq!(app.hot(), max_hot);
+
+    // accrual resumes from the ceiling, not from what would have been accrued
+    app.lock(seconds_to_near(giga));
+    assert_eq!(app.hot(), max_hot - seconds_to_yocto(giga));
+    fast_forward(giga, giga / 2);
+    assert_eq!(app.hot(), max_hot - seconds_to_yocto(giga / 2));
+
+    fast_forward(10 * giga, giga);
+    assert_eq!(app.hot(), max_hot);
+}
+
+#[test]
+fn test_daily_limit() {
+    let mut app = install();
+    let giga = 1_000_000_000; // to avoid Near fractions
+    let one_near = 10u128.pow(24);
+    let max_per_day = CONFIG.max_per_day as u128 * one_near;
+    fast_forward(10 * giga, 16 * giga);
+    assert_eq!(app.daily_available(), max_per_day);
+
+    app.lock_impl(max_per_day - one_near)
+        .expect("within daily limit");
+    let hot = app.hot();
+    let err = app
+        .pay_impl(2 * one_near, &"max.near".parse().unwrap())
+        .expect_err("should fail");
+    assert_eq!(err, Error::DailyLimitExceeded);
+    assert_eq!(hot, app.hot());
+
+    // trusted recipients count towards the same limit
+    let err = app
+        .pay_impl(2 * one_near, &trusted_recipient())
+        .expect_err("should fail");
+    assert_eq!(err, Error::DailyLimitExceeded);
+    app.pay_impl(one_near, &trusted_recipient())
+        .expect("exactly at daily limit");
+    assert_eq!(app.daily_available(), 0);
+
+    fast_forward(100, 24 * 3600);
+    assert_eq!(app.daily_available(), 0);
+    fast_forward(100, 3600);
+    assert_eq!(app.daily_available(), max_per_day);
+}
+
+#[test]
+fn test_high_rate_for_years() {
+    install();
+    let allowance = Allowance::new();
+    // 1 Near per second for 10 years, plus a fraction of a second
+    let yocto_per_second = 10u128.pow(24);
+    let seconds = 10 * 365 * 24 * 60 * 60;
+    fast_forward(10_000, seconds);
+    assert_eq!(
+        allowance.available(yocto_per_second, u128::MAX),
+        seconds as u128 * yocto_per_second
+    );
+    let mut context = get_context(false);
+    context.block_timestamp = env::block_timestamp() + 500_000_000;
+    testing_env!(context);
+    assert_eq!(
+        allowance.available(yocto_per_second, u128::MAX),
+        seconds as u128 * yocto_per_second + yocto_per_second / 2
+    );
+}
+
+#[test]
+fn test_delayed_payment() {
+    let mut app = install();
+    let giga = 1_000_000_000; // to avoid Near fractions
+    let receiver = "max.near".parse().unwrap();
+    let threshold = CONFIG.delayed_payment_threshold as u128 * 10u128.pow(24);
+    fast_forward(10 * giga, 12 * giga);
+
+    app.pay_impl(threshold, &receiver)
+        .expect("access should work");
+    assert!(app.pending_payments().is_empty());
+
+    app.pay_impl(threshold + 1, &receiver)
+        .expect("access should work");
+    assert_eq!(app.hot(), seconds_to_yocto(12 * giga) - 2 * threshold - 1);
+    assert_eq!(app.pending_payments().len(), 1);
+    let id = app.pending_payments()[0].id;
+
+    let err = app.release_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::PaymentStillDelayed);
+
+    fast_forward(100, CONFIG.payment_delay_seconds);
+    app.release_impl(id).expect("delay has passed");
+    assert!(app.pending_payments().is_empty());
+    let err = app.release_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::UnknownPayment);
+}
+
+#[test]
+fn test_cancel_payment() {
+    let mut app = install();
+    let giga = 1_000_000_000; // to avoid Near fractions
+    let receiver = "max.near".parse().unwrap();
+    let threshold = CONFIG.delayed_payment_threshold as u128 * 10u128.pow(24);
+    fast_forward(10 * giga, 12 * giga);
+
+    app.pay_impl(threshold + 1, &receiver)
+        .expect("access should work");
+    let id = app.pending_payments()[0].id;
+    let hot = app.hot();
+
+    let err = app.cancel_payment_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    set_attached_deposit(1);
+    app.cancel_payment_impl(id)
+        .expect("full access key attached 1 yocto");
+    assert!(app.pending_payments().is_empty());
+    // cancelled tokens are not returned to the hot balance
+    assert_eq!(app.hot(), hot);
+
+    fast_forward(100, CONFIG.payment_delay_seconds);
+    let err = app.release_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::UnknownPayment);
+}
+
+#[test]
+fn test_co_signed_payment() {
+    let mut app = install();
+    let giga = 1_000_000_000; // to avoid Near fractions
+    let receiver = "max.near".parse().unwrap();
+    let co_signers = CONFIG.co_signers.as_ref().unwrap();
+    let threshold = co_signers.payment_threshold as u128 * 10u128.pow(24);
+    fast_forward(10 * giga, 12 * giga);
+
+    app.pay_impl(threshold + 1, &receiver)
+        .expect("access should work");
+    let id = app.pending_payments()[0].id;
+    assert_eq!(app.pending_payments()[0].approvals_required, 2);
+
+    // the delay has passed but approvals are missing
+    fast_forward(100, CONFIG.payment_delay_seconds);
+    let err = app.release_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::PaymentNotApproved);
+
+    // only co-signers can approve, each one once
+    let err = app.approve_payment_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::NotCoSigner);
+    set_predecessor_account("dave.test.near", false);
+    app.approve_payment_impl(id).expect("co-signer should work");
+    let err = app.approve_payment_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::AlreadyApproved);
+    // co-signers cannot spend or release
+    let err = app.release_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+    let err = app.pay_impl(1, &receiver).expect_err("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+
+    set_predecessor_account("erin.test.near", false);
+    app.approve_payment_impl(id).expect("co-signer should work");
+    next_call();
+    app.release_impl(id).expect("payment is approved");
+    assert!(app.pending_payments().is_empty());
+}
+
+#[test]
+fn test_unlock() {
+    let mut app = install();
+    fast_forward(10, 10);
+    let tokens = seconds_to_yocto(100);
+
+    let err = app.unlock_impl(tokens).expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    app.assert_hot(10);
+
+    set_predecessor_account("max.near", false);
+    set_attached_deposit(1);
+    let err = app.unlock_impl(tokens).expect_err("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+    app.assert_hot(10);
+
+    set_predecessor_account("teller.near", false);
+    set_attached_deposit(1);
+    app.unlock_impl(tokens)
+        .expect("full access key attached 1 yocto");
+    app.assert_hot(110);
+    fast_forward(10, 10);
+    app.assert_hot(120);
+}
+
+#[test]
+fn test_set_rate() {
+    let mut app = install();
+    let rate = CONFIG.nano_near_per_second;
+    fast_forward(10, 100);
+
+    // any key can lower the rate, what accrued so far stays
+    app.set_rate_impl(rate / 2).expect("lowering is allowed");
+    app.assert_hot(100);
+    fast_forward(10, 100);
+    app.assert_hot(150);
+
+    let err = app.set_rate_impl(rate).expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    assert_eq!(app.rate(), rate / 2);
+
+    set_attached_deposit(1);
+    app.set_rate_impl(2 * rate)
+        .expect("full access key attached 1 yocto");
+    assert_eq!(app.rate(), 2 * rate);
+    app.assert_hot(150);
+    fast_forward(10, 100);
+    app.assert_hot(350);
+
+    app.lock_impl(seconds_to_yocto(300))
+        .expect("access should work");
+    app.assert_hot(50);
+}
+
+#[test]
+fn test_pay_trusted_recipient() {
+    let mut app = install();
+    let trusted = trusted_recipient();
+    let untrusted = "max.near".parse().unwrap();
+
+    fast_forward(100, 100);
+    assert_eq!(app.hot_for(trusted.clone()), trusted_seconds_to_yocto(100));
+    assert_eq!(app.hot_for(untrusted), seconds_to_yocto(100));
+
+    // more than the untrusted allowance, which stays untouched
+    let tokens = trusted_seconds_to_yocto(60);
+    app.pay_impl(tokens, &trusted).expect("access should work");
+    assert_eq!(app.hot_for(trusted.clone()), trusted_seconds_to_yocto(40));
+    app.assert_hot(100);
+
+    let err = app.pay_impl(tokens, &trusted).expect_err("should fail");
+    assert_eq!(err, Error::NotEnoughHot);
+    assert_eq!(app.hot_for(trusted), trusted_seconds_to_yocto(40));
+}
+
+#[test]
+fn test_trusted_recipient_max_hot() {
+    let mut app = install();
+    let trusted = trusted_recipient();
+    let max_hot = CONFIG.trusted_recipients[0].max_hot as u128 * 10u128.pow(24);
+    let seconds_to_max = (max_hot / trusted_seconds_to_yocto(1)) as u64;
+
+    fast_forward(100, 2 * seconds_to_max);
+    assert_eq!(app.hot_for(trusted.clone()), max_hot);
+
+    // accrual above the ceiling is gone for good once tokens are spent
+    let tokens = trusted_seconds_to_yocto(100);
+    app.pay_impl(tokens, &trusted).expect("access should work");
+    assert_eq!(app.hot_for(trusted.clone()), max_hot - tokens);
+
+    fast_forward(10, 10);
+    assert_eq!(
+        app.hot_for(trusted.clone()),
+        max_hot - trusted_seconds_to_yocto(90)
+    );
+
+    fast_forward(100, seconds_to_max);
+    assert_eq!(app.hot_for(trusted), max_hot);
+}
+
+#[test]
+fn test_staking_pool_registry() {
+    let mut app = install();
+    let pool: AccountId = "new-pool.near".parse().unwrap();
+    let n = CONFIG.staking_pools.len();
+    assert_eq!(app.staking_pools().len(), n);
+
+    let err = app
+        .add_staking_pool_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    let err = app.stake_impl(1, n).expect_err("should fail");
+    assert_eq!(err, Error::UnknownStakingPool);
+
+    set_attached_deposit(1);
+    app.add_staking_pool_impl(pool.clone())
+        .expect("full access key attached 1 yocto");
+    assert_eq!(app.staking_pools()[n], pool);
+    let err = app
+        .add_staking_pool_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::StakingPoolAlreadyListed);
+    app.stake_impl(1, n).expect("pool is listed");
+
+    app.remove_staking_pool_impl(0)
+        .expect("full access key attached 1 yocto");
+    assert_eq!(app.staking_pools()[n - 1], pool);
+    let err = app.remove_staking_pool_impl(n).expect_err("should fail");
+    assert_eq!(err, Error::UnknownStakingPool);
+}
+
+#[test]
+fn test_add_factory_staking_pool() {
+    let mut app = install();
+    let n = CONFIG.staking_pools.len();
+    let pool: AccountId = format!("new-pool.{}", CONFIG.staking_pool_factory)
+        .parse()
+        .unwrap();
+
+    for not_from_factory in [
+        "new-pool.near".to_owned(),
+        format!("a.new-pool.{}", CONFIG.staking_pool_factory),
+    ] {
+        let err = app
+            .add_factory_staking_pool_impl(not_from_factory.parse().unwrap())
+            .expect_err("should fail");
+        assert_eq!(err, Error::NotAFactoryStakingPool);
+    }
+    app.add_factory_staking_pool_impl(pool.clone())
+        .expect("direct subaccount of factory");
+    // only added after the whitelist confirmed it
+    assert_eq!(app.staking_pools().len(), n);
+
+    let err = app
+        .on_staking_pool_whitelisted_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotACallback);
+
+    set_callback_result(PromiseResult::Successful(b"false".to_vec()));
+    let err = app
+        .on_staking_pool_whitelisted_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::StakingPoolNotWhitelisted);
+    set_callback_result(PromiseResult::Failed);
+    let err = app
+        .on_staking_pool_whitelisted_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::StakingPoolNotWhitelisted);
+
+    set_callback_result(PromiseResult::Successful(b"true".to_vec()));
+    app.on_staking_pool_whitelisted_impl(pool.clone())
+        .expect("whitelisted");
+    assert_eq!(app.staking_pools()[n], pool);
+}
+
+#[test]
+fn test_staking_summary() {
+    let mut app = install();
+    let pool = app.staking_pools()[0].clone();
+    let stake = StakingAction::Stake(U128(1000));
+
+    app.stake_impl(1000, 0).expect("access should work");
+    let err = app
+        .on_staking_action_impl(pool.clone(), stake)
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotACallback);
+
+    set_callback_result(PromiseResult::Failed);
+    app.on_staking_action_impl(pool.clone(), stake)
+        .expect("failure is not an error");
+    assert!(app.staking_summary().is_empty());
+
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_staking_action_impl(pool.clone(), stake)
+        .expect("access should work");
+    set_pool_account_result(997, 3);
+    app.on_staking_pool_account_impl(pool.clone(), stake)
+        .expect("access should work");
+    app.assert_staking_record(&pool, [1000, 0, 0, 997, 3]);
+
+    app.unstake_impl(0, None).expect("access should work");
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_staking_action_impl(pool.clone(), StakingAction::Unstake)
+        .expect("access should work");
+    set_pool_account_result(0, 1001);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Unstake)
+        .expect("access should work");
+    app.assert_staking_record(&pool, [1000, 998, 0, 0, 1001]);
+
+    app.withdraw_impl(0, None).expect("access should work");
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_staking_action_impl(pool.clone(), StakingAction::Withdraw)
+        .expect("access should work");
+    set_pool_account_result(0, 0);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Withdraw)
+        .expect("access should work");
+    app.assert_staking_record(&pool, [1000, 998, 1001, 0, 0]);
+}
+
+#[test]
+fn test_partial_unstake_withdraw() {
+    let mut app = install();
+    let n = app.staking_pools().len();
+
+    app.unstake_impl(0, Some(10u128.pow(24) + 1))
+        .expect("access should work");
+    app.withdraw_impl(0, Some(7)).expect("access should work");
+    assert_eq!(
+        staking_pool_calls(),
+        [
+            (
+                "unstake".to_owned(),
+                r#"{"amount":"1000000000000000000000001"}"#.to_owned()
+            ),
+            ("withdraw".to_owned(), r#"{"amount":"7"}"#.to_owned()),
+        ]
+    );
+
+    let err = app.unstake_impl(n, Some(1)).expect_err("should fail");
+    assert_eq!(err, Error::UnknownStakingPool);
+    let err = app.withdraw_impl(n, Some(1)).expect_err("should fail");
+    assert_eq!(err, Error::UnknownStakingPool);
+}
+
+#[test]
+fn test_staking_rewards() {
+    let mut app = install();
+    let pool = app.staking_pools()[0].clone();
+    app.simulate_stake(&pool, 100);
+
+    // 10 yocto rewards accrued, everything gets unstaked
+    set_pool_account_result(0, 110);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Unstake)
+        .expect("callback should work");
+    app.assert_staking_record(&pool, [100, 110, 0, 0, 110]);
+    app.assert_hot(0);
+
+    // withdrawals count as rewards first
+    set_pool_account_result(0, 105);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Withdraw)
+        .expect("callback should work");
+    assert_eq!(app.staking_summary()[&pool].rewards.0, 5);
+    assert_eq!(app.staking_summary()[&pool].principal.0, 100);
+    assert_eq!(app.hot(), 5);
+    assert_eq!(
+        events(),
+        [json!({
+            "standard": "near_teller",
+            "version": "1.0.0",
+            "event": "staking_rewards",
+            "data": { "staking_pool": pool, "yocto": "5", "hot": "5" },
+        })]
+    );
+
+    set_pool_account_result(0, 0);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Withdraw)
+        .expect("callback should work");
+    app.assert_staking_record(&pool, [100, 110, 110, 0, 0]);
+    assert_eq!(app.staking_summary()[&pool].rewards.0, 10);
+    assert_eq!(app.staking_summary()[&pool].principal.0, 0);
+    assert_eq!(app.hot(), 10);
+}
+
+/// As shipped, `staking_rewards_to_hot` is disabled and the rewards returned by
+/// `realize` are only booked in the staking record.
+#[test]
+fn test_staking_rewards_cold() {
+    let mut record = StakingRecord {
+        deposited: U128(100),
+        principal: U128(100),
+        ..Default::default()
+    };
+
+    // 5 of 10 yocto rewards withdrawn
+    record.unstaked_balance = U128(105);
+    assert_eq!(record.realize(5), 5);
+    assert_eq!(record.rewards.0, 5);
+    assert_eq!(record.principal.0, 100);
+
+    // then the principal
+    record.unstaked_balance = U128(0);
+    assert_eq!(record.realize(105), 5);
+    assert_eq!(record.rewards.0, 10);
+    assert_eq!(record.principal.0, 0);
+    assert_eq!(record.withdrawn.0, 110);
+}
+
+#[test]
+fn test_untracked_stake() {
+    let mut app = install();
+    let pool = app.staking_pools()[0].clone();
+
+    // the pool already holds stake that teller did not deposit
+    let action = StakingAction::Stake(U128(1));
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_staking_action_impl(pool.clone(), action)
+        .expect("callback should work");
+    set_pool_account_result(10_000 * 10u128.pow(24) + 1, 0);
+    app.on_staking_pool_account_impl(pool.clone(), action)
+        .expect("callback should work");
+    assert!(app.staking_summary()[&pool].untracked);
+
+    // so it is not harvested as rewards
+    next_call();
+    set_pool_account_result(10_000 * 10u128.pow(24) + 10, 0);
+    app.on_harvest_account_impl(pool.clone())
+        .expect("callback should work");
+    assert_eq!(staking_pool_calls(), []);
+
+    // rounding of shares by the pool is no reason to stop tracking
+    let pool = app.staking_pools()[1].clone();
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_staking_action_impl(pool.clone(), action)
+        .expect("callback should work");
+    set_pool_account_result(2, 0);
+    app.on_staking_pool_account_impl(pool.clone(), action)
+        .expect("callback should work");
+    assert!(!app.staking_summary()[&pool].untracked);
+}
+
+#[test]
+fn test_harvest() {
+    let mut app = install();
+    let pool = app.staking_pools()[0].clone();
+    app.simulate_stake(&pool, 100);
+
+    // nothing to harvest, yet
+    set_pool_account_result(100, 0);
+    app.on_harvest_account_impl(pool.clone())
+        .expect("callback should work");
+    assert_eq!(staking_pool_calls(), []);
+
+    // only rewards are unstaked
+    set_pool_account_result(110, 0);
+    app.on_harvest_account_impl(pool.clone())
+        .expect("callback should work");
+    assert_eq!(
+        staking_pool_calls(),
+        [("unstake".to_owned(), r#"{"amount":"10"}"#.to_owned())]
+    );
+
+    // harvesting again before the unstaked rewards can be withdrawn would delay them
+    set_pool_account_result_can_withdraw(100, 10, false);
+    let err = app
+        .on_harvest_account_impl(pool.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::StakingPoolWithdrawalNotReady);
+
+    // rewards accrued meanwhile are unstaked after withdrawing
+    set_pool_account_result(102, 10);
+    app.on_harvest_account_impl(pool.clone())
+        .expect("callback should work");
+    assert_eq!(
+        staking_pool_calls(),
+        [
+            ("withdraw".to_owned(), r#"{"amount":"10"}"#.to_owned()),
+            ("unstake".to_owned(), r#"{"amount":"2"}"#.to_owned()),
+        ]
+    );
+
+    set_pool_account_result(100, 2);
+    let action = StakingAction::Harvest {
+        withdraw: U128(10),
+        unstake: U128(2),
+    };
+    app.on_staking_pool_account_impl(pool.clone(), action)
+        .expect("callback should work");
+    app.assert_staking_record(&pool, [100, 2, 10, 100, 2]);
+    assert_eq!(app.staking_summary()[&pool].principal.0, 100);
+    assert_eq!(app.hot(), 10);
+}
+
+#[test]
+fn test_redelegation() {
+    let mut app = install();
+    let n = app.staking_pools().len();
+    let from = app.staking_pools()[0].clone();
+    app.simulate_stake(&from, 100);
+
+    let err = app.redelegate_impl(0, 0).expect_err("should fail");
+    assert_eq!(err, Error::RedelegationToSamePool);
+    let err = app.redelegate_impl(0, n).expect_err("should fail");
+    assert_eq!(err, Error::UnknownStakingPool);
+    assert!(app.redelegation().is_none());
+
+    set_predecessor_account("teller.near", false);
+    app.redelegate_impl(0, 1).expect("access should work");
+    assert_eq!(
+        staking_pool_calls(),
+        [("unstake_all".to_owned(), String::new())]
+    );
+    set_pool_account_result(0, 110);
+    app.on_staking_pool_account_impl(from.clone(), StakingAction::Unstake)
+        .expect("callback should work");
+    set_predecessor_account("teller.near", false);
+    let err = app.redelegate_impl(1, 0).expect_err("should fail");
+    assert_eq!(err, Error::RedelegationInProgress);
+
+    // anyone can progress
+    set_predecessor_account("bot.near", false);
+    app.progress_redelegation_impl()
+        .expect("anyone should be able to progress");
+
+    set_pool_account_result_can_withdraw(0, 110, false);
+    let err = app.on_redelegation_account_impl().expect_err("should fail");
+    assert_eq!(err, Error::StakingPoolWithdrawalNotReady);
+    set_pool_account_result(0, 0);
+    let err = app.on_redelegation_account_impl().expect_err("should fail");
+    assert_eq!(err, Error::NothingToRedelegate);
+    set_pool_account_result(0, 110);
+    app.on_redelegation_account_impl()
+        .expect("callback should work");
+    assert_eq!(
+        staking_pool_calls(),
+        [("withdraw".to_owned(), r#"{"amount":"110"}"#.to_owned())]
+    );
+
+    // a failed withdrawal keeps the redelegation
+    set_callback_result(PromiseResult::Failed);
+    app.on_redelegation_withdrawn_impl(110)
+        .expect("callback should work");
+    assert!(app.redelegation().is_some());
+
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_redelegation_withdrawn_impl(110)
+        .expect("callback should work");
+    assert!(app.redelegation().is_none());
+    assert_eq!(
+        staking_pool_calls(),
+        [("deposit_and_stake".to_owned(), String::new())]
+    );
+
+    // rewards moved to the new pool are not credited to the hot balance
+    set_pool_account_result(0, 0);
+    app.on_staking_pool_account_impl(from.clone(), StakingAction::Redelegate(U128(110)))
+        .expect("callback should work");
+    app.assert_staking_record(&from, [100, 110, 110, 0, 0]);
+    assert_eq!(app.hot(), 0);
+
+    let err = app.cancel_redelegation_impl().expect_err("should fail");
+    assert_eq!(err, Error::NoRedelegation);
+}
+
+#[test]
+fn test_events() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let hot = seconds_to_yocto(10);
+    let pool = app.staking_pools()[1].clone();
+
+    app.pay_impl(100, &"max.near".parse().unwrap())
+        .expect("access should work");
+    app.lock_impl(50).expect("access should work");
+    app.stake_impl(7, 1).expect("access should work");
+    app.unstake_impl(1, None).expect("access should work");
+    app.withdraw_impl(1, Some(3)).expect("access should work");
+
+    let event = |event: &str, data| {
+        json!({
+            "standard": "near_teller",
+            "version": "1.0.0",
+            "event": event,
+            "data": data,
+        })
+    };
+    let remaining = (hot - 150).to_string();
+    assert_eq!(
+        events(),
+        [
+            event(
+                "pay",
+                json!({ "receiver": "max.near", "yocto": "100", "hot": (hot - 100).to_string() })
+            ),
+            event("lock", json!({ "yocto": "50", "hot": remaining })),
+            event(
+                "stake",
+                json!({ "pool": 1, "staking_pool": pool, "yocto": "7", "hot": remaining })
+            ),
+            event(
+                "unstake",
+                json!({ "pool": 1, "staking_pool": pool, "yocto": null, "hot": remaining })
+            ),
+            event(
+                "withdraw",
+                json!({ "pool": 1, "staking_pool": pool, "yocto": "3", "hot": remaining })
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_history() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let receiver: AccountId = "max.near".parse().unwrap();
+    app.pay_impl(100, &receiver).expect("access should work");
+    app.lock_impl(50).expect("access should work");
+
+    assert_eq!(app.history_len(), 2);
+    let history = app.history(0, 10);
+    assert_eq!(history.len(), 2);
+    assert!(matches!(history[0].kind, HistoryKind::Pay));
+    assert_eq!(history[0].yocto.0, 100);
+    assert_eq!(history[0].receiver.as_ref(), Some(&receiver));
+    assert_eq!(history[0].timestamp.0, env::block_timestamp());
+    assert!(matches!(history[1].kind, HistoryKind::Lock));
+    assert_eq!(history[1].yocto.0, 50);
+    assert_eq!(history[1].receiver, None);
+    assert_eq!(app.history(1, 10)[0].index, 1);
+    assert_eq!(app.history(0, 1).len(), 1);
+    assert!(app.history(2, 10).is_empty());
+
+    // the oldest entries are dropped once the capacity is reached
+    let capacity = CONFIG.history_capacity as u64;
+    for _ in 0..capacity {
+        next_call();
+        app.lock_impl(1).expect("access should work");
+    }
+    assert_eq!(app.history_len(), capacity + 2);
+    let history = app.history(0, 3);
+    let indices: Vec<_> = history.iter().map(|entry| entry.index).collect();
+    assert_eq!(indices, [2, 3, 4]);
+    assert_eq!(app.history(0, u32::MAX).len() as u64, capacity);
+}
+
+#[test]
+fn test_payment_refund() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let receiver: AccountId = "does-not-exist.near".parse().unwrap();
+    app.pay_impl(100, &receiver).expect("access should work");
+    app.pay_impl(7, &trusted_recipient())
+        .expect("access should work");
+    assert_eq!(app.hot(), seconds_to_yocto(10) - 100);
+    assert_eq!(
+        app.hot_for(trusted_recipient()),
+        trusted_seconds_to_yocto(10) - 7
+    );
+
+    set_callback_result(PromiseResult::Successful(vec![]));
+    app.on_pay_impl(receiver.clone(), 100, Budget::Hot)
+        .expect("callback should work");
+    assert_eq!(app.hot(), seconds_to_yocto(10) - 100);
+
+    set_callback_result(PromiseResult::Failed);
+    app.on_pay_impl(receiver.clone(), 100, Budget::Hot)
+        .expect("callback should work");
+    assert_eq!(app.hot(), seconds_to_yocto(10));
+    assert_eq!(events()[0]["event"], "payment_refunded");
+
+    set_callback_result(PromiseResult::Failed);
+    app.on_pay_impl(trusted_recipient(), 7, Budget::Trusted(0))
+        .expect("callback should work");
+    assert_eq!(
+        app.hot_for(trusted_recipient()),
+        trusted_seconds_to_yocto(10)
+    );
+    assert_eq!(app.hot(), seconds_to_yocto(10));
+
+    set_predecessor_account("teller.near", false);
+    let err = app
+        .on_pay_impl(receiver, 100, Budget::Hot)
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotACallback);
+}
+
+#[test]
+fn test_pay_batch() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let hot = seconds_to_yocto(10);
+    let a: AccountId = "a.near".parse().unwrap();
+    let b: AccountId = "b.near".parse().unwrap();
+
+    let err = app.pay_batch_impl(&[]).expect_err("should fail");
+    assert_eq!(err, Error::EmptyBatch);
+    let err = app
+        .pay_batch_impl(&[(a.clone(), 1), (b.clone(), 1), (a.clone(), 1)])
+        .expect_err("should fail");
+    assert_eq!(err, Error::DuplicateReceiver);
+    let too_many: Vec<_> = (0..11)
+        .map(|i| (format!("{i}.near").parse().unwrap(), 1))
+        .collect();
+    let err = app.pay_batch_impl(&too_many).expect_err("should fail");
+    assert_eq!(err, Error::BatchTooLarge);
+
+    // the total counts, nothing is paid if it exceeds the hot balance
+    let err = app
+        .pay_batch_impl(&[(a.clone(), hot), (b.clone(), 1)])
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotEnoughHot);
+    assert_eq!(app.hot(), hot);
+    assert!(get_created_receipts().is_empty());
+
+    // trusted recipients are charged to their own balance
+    let trusted = trusted_seconds_to_yocto(10);
+    app.pay_batch_impl(&[(a.clone(), hot - 1), (trusted_recipient(), trusted), (b, 1)])
+        .expect("access should work");
+    assert_eq!(app.hot(), 0);
+    assert_eq!(app.hot_for(trusted_recipient()), 0);
+    let transfers = get_created_receipts()
+        .into_iter()
+        .filter(|receipt| matches!(receipt.actions[..], [VmAction::Transfer { .. }]))
+        .count();
+    assert_eq!(transfers, 3);
+    assert_eq!(app.history_len(), 3);
+
+    // a full batch fits into the gas of a single call
+    fast_forward(100, 10);
+    let full: Vec<_> = (0..10)
+        .map(|i| (format!("{i}.near").parse().unwrap(), 1))
+        .collect();
+    app.pay_batch_impl(&full).expect("access should work");
+}
+
+#[test]
+fn test_pay_ft() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let token = &CONFIG.fungible_tokens[0];
+    let hot_ft = 10 * token.units_per_second;
+    let receiver: AccountId = "max.near".parse().unwrap();
+    assert_eq!(app.hot_ft(0), hot_ft);
+
+    app.pay_ft_impl(0, 40, &receiver, false)
+        .expect("access should work");
+    assert_eq!(app.hot_ft(0), hot_ft - 40);
+    assert_eq!(app.hot(), seconds_to_yocto(10));
+    let receipts = get_created_receipts();
+    assert_eq!(receipts[0].receiver_id.as_str(), token.account_id);
+    assert!(matches!(
+        &receipts[0].actions[..],
+        [VmAction::FunctionCall { function_name, args, deposit: 1, .. }]
+            if function_name == "ft_transfer"
+                && args == br#"{"amount":"40","receiver_id":"max.near"}"#
+    ));
+
+    let err = app
+        .pay_ft_impl(0, hot_ft, &receiver, false)
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotEnoughHot);
+    let n = CONFIG.fungible_tokens.len();
+    let err = app
+        .pay_ft_impl(n, 1, &receiver, false)
+        .expect_err("should fail");
+    assert_eq!(err, Error::UnknownToken);
+
+    // e.g. receiver not registered with the token
+    set_callback_result(PromiseResult::Failed);
+    app.on_ft_pay_impl(0, receiver, 40, None, Budget::Hot)
+        .expect("callback should work");
+    assert_eq!(app.hot_ft(0), hot_ft);
+}
+
+#[test]
+fn test_pay_ft_register() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let token = &CONFIG.fungible_tokens[0];
+    let receiver: AccountId = "max.near".parse().unwrap();
+
+    // the storage deposit is charged to the hot balance in Near
+    let err = app
+        .pay_ft_impl(0, 40, &receiver, true)
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotEnoughHot);
+    assert_eq!(app.hot_ft(0), 10 * token.units_per_second);
+
+    fast_forward(100, 2000);
+    let hot = seconds_to_yocto(2010);
+    let hot_ft = 2010 * token.units_per_second;
+    app.pay_ft_impl(0, 40, &receiver, true)
+        .expect("access should work");
+    assert_eq!(app.hot(), hot - token.storage_deposit);
+    assert_eq!(app.hot_ft(0), hot_ft - 40);
+    let receipts = get_created_receipts();
+    let calls: Vec<_> = receipts[0]
+        .actions
+        .iter()
+        .filter_map(|action| match action {
+            VmAction::FunctionCall {
+                function_name,
+                deposit,
+                ..
+            } => Some((function_name.as_str(), *deposit)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            ("storage_deposit", token.storage_deposit),
+            ("ft_transfer", 1)
+        ]
+    );
+
+    set_callback_result(PromiseResult::Failed);
+    app.on_ft_pay_impl(0, receiver, 40, Some(token.storage_deposit), Budget::Hot)
+        .expect("callback should work");
+    assert_eq!(app.hot(), hot);
+    assert_eq!(app.hot_ft(0), hot_ft);
+}
+
+#[test]
+fn test_schedules() {
+    let mut app = install();
+    fast_forward(100, 10);
+    let hot = seconds_to_yocto(10);
+    let a: AccountId = "a.near".parse().unwrap();
+    let b: AccountId = "b.near".parse().unwrap();
+
+    let err = app
+        .create_schedule_impl(a.clone(), 100, 60, 2)
+        .expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    set_attached_deposit(1);
+    let err = app
+        .create_schedule_impl(a.clone(), 100, 60, 0)
+        .expect_err("should fail");
+    assert_eq!(err, Error::InvalidSchedule);
+    let id_a = app
+        .create_schedule_impl(a, 100, 60, 2)
+        .expect("admin access should work");
+    // more than the hot balance, stays due
+    let id_b = app
+        .create_schedule_impl(b, 100 * hot, 60, 1)
+        .expect("admin access should work");
+    assert_eq!(app.schedules().len(), 2);
+
+    // anyone can execute
+    set_predecessor_account("bot.near", false);
+    app.execute_due_impl().expect("should work");
+    assert_eq!(app.hot(), hot - 100);
+    assert_eq!(app.schedules()[0].remaining, 1);
+    assert_eq!(app.schedules()[1].remaining, 1);
+
+    // the next payment is only due after the period
+    next_call();
+    app.execute_due_impl().expect("should work");
+    assert!(get_created_receipts().is_empty());
+    fast_forward(10, 59);
+    app.execute_due_impl().expect("should work");
+    assert!(get_created_receipts().is_empty());
+    fast_forward(1, 1);
+    app.execute_due_impl().expect("should work");
+    assert_eq!(app.hot(), seconds_to_yocto(70) - 200);
+    assert_eq!(app.schedules().len(), 1);
+    assert_eq!(app.schedules()[0].id, id_b);
+
+    set_predecessor_account("bot.near", false);
+    let err = app.cancel_schedule_impl(id_b).expect_err("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+    set_predecessor_account("teller.near", false);
+    app.cancel_schedule_impl(id_b).expect("access should work");
+    assert!(app.schedules().is_empty());
+    let err = app.cancel_schedule_impl(id_a).expect_err("should fail");
+    assert_eq!(err, Error::UnknownSchedule);
+}
+
+#[test]
+fn test_key_tiers() {
+    let mut app = install();
+    fast_forward(100, 1000);
+    let hot = seconds_to_yocto(1000);
+    let hot_key = &CONFIG.hot_keys[0];
+    let tier_hot = 1000 * hot_key.nano_near_per_second * 10u128.pow(15);
+    let tier_key: PublicKey = hot_key.public_key.parse().unwrap();
+    let other_key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtq"
+        .parse()
+        .unwrap();
+    assert_eq!(app.hot_for_key(tier_key.clone()), tier_hot);
+    assert_eq!(app.hot_for_key(other_key.clone()), hot);
+
+    // a key with its own allowance does not touch the general one
+    set_signer_key(&tier_key);
+    app.pay_impl(100, &"max.near".parse().unwrap())
+        .expect("access should work");
+    app.lock_impl(50).expect("access should work");
+    assert_eq!(app.hot_for_key(tier_key.clone()), tier_hot - 150);
+    assert_eq!(app.hot(), hot);
+    let err = app.lock_impl(tier_hot).expect_err("should fail");
+    assert_eq!(err, Error::NotEnoughHot);
+
+    // other keys use the general allowance
+    set_signer_key(&other_key);
+    app.pay_impl(100, &"max.near".parse().unwrap())
+        .expect("access should work");
+    assert_eq!(app.hot(), hot - 100);
+    assert_eq!(app.hot_for_key(tier_key.clone()), tier_hot - 150);
+
+    // refunds go back to the allowance that was charged
+    set_callback_result(PromiseResult::Failed);
+    app.on_pay_impl(
+        "max.near".parse().unwrap(),
+        100,
+        Budget::Key(tier_key.clone()),
+    )
+    .expect("callback should work");
+    assert_eq!(app.hot_for_key(tier_key.clone()), tier_hot - 50);
+    assert_eq!(app.hot(), hot - 100);
+
+    // token allowances are shared, so the key cannot spend them
+    set_signer_key(&tier_key);
+    let err = app
+        .pay_ft_impl(0, 40, &"max.near".parse().unwrap(), true)
+        .expect_err("should fail");
+    assert_eq!(err, Error::KeyTierCannotPayTokens);
+    assert_eq!(app.hot_for_key(tier_key.clone()), tier_hot - 50);
+    assert_eq!(
+        app.hot_ft(0),
+        1000 * CONFIG.fungible_tokens[0].units_per_second
+    );
+
+    // schedules use the general allowance, no matter who executes them
+    set_attached_deposit(1);
+    app.create_schedule_impl("max.near".parse().unwrap(), 100, 60, 1)
+        .expect("access should work");
+    set_signer_key(&tier_key);
+    app.execute_due_impl().expect("anyone can execute");
+    assert!(app.schedules().is_empty());
+    assert_eq!(app.hot_for_key(tier_key), tier_hot - 50);
+    assert_eq!(app.hot(), hot - 200);
+}
+
+#[test]
+fn test_access_keys() {
+    let mut app = install();
+    let key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtq"
+        .parse()
+        .unwrap();
+
+    // adding and deleting keys needs the full access key
+    let err = app
+        .add_access_key_impl(&key, 1000)
+        .expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    let err = app.delete_access_key_impl(&key).expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+
+    // 0 would be an unlimited allowance
+    set_attached_deposit(1);
+    let err = app.add_access_key_impl(&key, 0).expect_err("should fail");
+    assert_eq!(err, Error::ZeroKeyAllowance);
+
+    app.add_access_key_impl(&key, 1000)
+        .expect("access should work");
+    match &get_created_receipts()[..] {
+        [receipt] => match &receipt.actions[..] {
+            [VmAction::AddKeyWithFunctionCall {
+                public_key,
+                allowance,
+                receiver_id,
+                function_names,
+                ..
+            }] => {
+                assert_eq!(public_key, &key);
+                assert_eq!(*allowance, Some(1000));
+                assert_eq!(receiver_id.as_str(), "teller.near");
+                assert!(function_names.contains(&"pay".to_owned()));
+                assert!(function_names.contains(&"revoke_self".to_owned()));
+                assert!(function_names.contains(&"set_rate".to_owned()));
+                assert!(!function_names.contains(&"unlock".to_owned()));
+            }
+            actions => panic!("unexpected actions {actions:?}"),
+        },
+        receipts => panic!("unexpected receipts {receipts:?}"),
+    }
+
+    // a key can delete itself
+    next_call();
+    set_signer_key(&key);
+    app.revoke_self_impl().expect("access should work");
+    match &get_created_receipts()[..] {
+        [receipt] => assert_eq!(
+            receipt.actions,
+            [VmAction::DeleteKey {
+                public_key: key.clone()
+            }]
+        ),
+        receipts => panic!("unexpected receipts {receipts:?}"),
+    }
+}
+
+#[test]
+fn test_freeze() {
+    let mut app = install();
+    fast_forward(100, 1000);
+    let hot = seconds_to_yocto(1000);
+    let receiver: AccountId = "max.near".parse().unwrap();
+    set_attached_deposit(1);
+    app.create_schedule_impl(receiver.clone(), 100, 60, 2)
+        .expect("access should work");
+
+    next_call();
+    app.freeze_impl().expect("access should work");
+    assert!(app.frozen());
+    let err = app.pay_impl(100, &receiver).expect_err("should fail");
+    assert_eq!(err, Error::Frozen);
+    let err = app.lock_impl(100).expect_err("should fail");
+    assert_eq!(err, Error::Frozen);
+    let err = app
+        .pay_batch_impl(&[(receiver.clone(), 100)])
+        .expect_err("should fail");
+    assert_eq!(err, Error::Frozen);
+    let err = app
+        .pay_ft_impl(0, 100, &receiver, false)
+        .expect_err("should fail");
+    assert_eq!(err, Error::Frozen);
+    let err = app.execute_due_impl().expect_err("should fail");
+    assert_eq!(err, Error::Frozen);
+    assert_eq!(app.hot(), hot);
+
+    // only the full access key can unfreeze
+    let err = app.unfreeze_impl().expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    set_attached_deposit(1);
+    app.unfreeze_impl().expect("access should work");
+    assert!(!app.frozen());
+
+    next_call();
+    app.pay_impl(100, &receiver).expect("access should work");
+    assert_eq!(app.hot(), hot - 100);
+}
+
+#[test]
+fn test_inheritance() {
+    let mut app = install();
+    let inactivity = CONFIG.inheritance.as_ref().unwrap().inactivity_seconds;
+    let heir = "heir.test.near";
+
+    fast_forward(100, inactivity - 1);
+    set_predecessor_account(heir, false);
+    let err = app.claim_inheritance_impl().expect_err("should fail");
+    assert_eq!(err, Error::InheritanceNotDue);
+
+    // a heartbeat restarts the timer
+    next_call();
+    app.heartbeat_impl().expect("access should work");
+    fast_forward(100, inactivity - 1);
+    set_predecessor_account(heir, false);
+    let err = app.claim_inheritance_impl().expect_err("should fail");
+    assert_eq!(err, Error::InheritanceNotDue);
+
+    // so does any other call signed by the owner
+    next_call();
+    app.lock_impl(1).expect("access should work");
+    fast_forward(100, inactivity - 1);
+    set_predecessor_account(heir, false);
+    let err = app.claim_inheritance_impl().expect_err("should fail");
+    assert_eq!(err, Error::InheritanceNotDue);
+
+    // only the beneficiary can claim
+    fast_forward(1, 1);
+    let err = app.claim_inheritance_impl().expect_err("should fail");
+    assert_eq!(err, Error::NotBeneficiary);
+    set_predecessor_account("max.near", false);
+    let err = app.claim_inheritance_impl().expect_err("should fail");
+    assert_eq!(err, Error::NotBeneficiary);
+    let err = app.heartbeat_impl().expect_err("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+
+    set_predecessor_account(heir, false);
+    let storage_cost = env::storage_usage() as u128 * env::storage_byte_cost();
+    let liquid = env::account_balance() - storage_cost;
+    app.claim_inheritance_impl().expect("claim should work");
+    let transfers: Vec<_> = get_created_receipts()
+        .into_iter()
+        .map(|receipt| (receipt.receiver_id.to_string(), receipt.actions))
+        .collect();
+    assert_eq!(
+        transfers,
+        [(
+            heir.to_owned(),
+            vec![VmAction::Transfer { deposit: liquid }]
+        )]
+    );
+}
+
+#[test]
+fn test_recovery() {
+    let mut app = install();
+    let delay = CONFIG.recovery.as_ref().unwrap().delay_seconds;
+    let key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtq"
+        .parse()
+        .unwrap();
+
+    // only guardians can propose and approve
+    let err = app
+        .propose_recovery_impl(key.clone())
+        .expect_err("should fail");
+    assert_eq!(err, Error::NotGuardian);
+    set_predecessor_account("alice.test.near", false);
+    let id = app
+        .propose_recovery_impl(key.clone())
+        .expect("guardian should work");
+    let err = app.approve_recovery_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::AlreadyApproved);
+    let err = app.execute_recovery_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::RecoveryNotReady);
+
+    // the second approval starts the delay
+    set_predecessor_account("bob.test.near", false);
+    app.approve_recovery_impl(id).expect("guardian should work");
+    let executable_at = app.recoveries()[0].executable_at.unwrap().0;
+    assert_eq!(
+        executable_at,
+        env::block_timestamp() + delay * 1_000_000_000
+    );
+    fast_forward(100, delay - 1);
+    let err = app.execute_recovery_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::RecoveryNotReady);
+
+    fast_forward(1, 1);
+    set_predecessor_account("max.near", false);
+    app.execute_recovery_impl(id)
+        .expect("anyone should be able to execute");
+    match &get_created_receipts()[..] {
+        [receipt] => assert_eq!(
+            receipt.actions,
+            [VmAction::AddKeyWithFullAccess {
+                public_key: key.clone(),
+                nonce: 0
+            }]
+        ),
+        receipts => panic!("unexpected receipts {receipts:?}"),
+    }
+    assert!(app.recoveries().is_empty());
+
+    // the owner can cancel with the full access key
+    set_predecessor_account("carol.test.near", false);
+    let id = app
+        .propose_recovery_impl(key)
+        .expect("guardian should work");
+    next_call();
+    let err = app.cancel_recovery_impl(id).expect_err("should fail");
+    assert_eq!(err, Error::FullAccessKeyRequired);
+    set_attached_deposit(1);
+    app.cancel_recovery_impl(id).expect("access should work");
+    assert!(app.recoveries().is_empty());
+}
+
+#[test]
+fn test_migrate_v0() {
+    testing_env!(get_context(false));
+    let v0 = TellerV0 {
+        t0: env::block_timestamp(),
+        locked: 100,
+    };
+    env::storage_write(b"STATE", &v0.try_to_vec().unwrap());
+
+    fast_forward(100, 1000);
+    let app = Teller::migrate();
+    assert_eq!(app.hot(), seconds_to_yocto(1000) - 100);
+    assert_eq!(app.staking_pools().len(), CONFIG.staking_pools.len());
+    assert!(app.pending_payments().is_empty());
+}
+
+#[test]
+fn test_migrate_current() {
+    let mut app = install();
+    fast_forward(100, 1000);
+    app.lock_impl(100).expect("access should work");
+    set_attached_deposit(1);
+    app.create_schedule_impl("max.near".parse().unwrap(), 100, 60, 2)
+        .expect("access should work");
+    env::state_write(&app);
+
+    // the current layout is kept as it is
+    next_call();
+    let app = Teller::migrate();
+    assert_eq!(app.hot(), seconds_to_yocto(1000) - 100);
+    assert_eq!(app.schedules().len(), 1);
+    assert_eq!(app.history_len(), 1);
+}
+
+#[test]
+fn test_migrate_v0_stake() {
+    testing_env!(get_context(false));
+    let v0 = TellerV0 {
+        t0: env::block_timestamp(),
+        locked: 0,
+    };
+    env::storage_write(b"STATE", &v0.try_to_vec().unwrap());
+    let mut app = Teller::migrate();
+    let pool = app.staking_pools()[0].clone();
+    assert!(app.staking_summary()[&pool].untracked);
+
+    // stake from before the migration is not taken for rewards
+    app.simulate_stake(&pool, 100);
+    set_pool_account_result(0, 150);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Unstake)
+        .expect("callback should work");
+    set_pool_account_result(0, 0);
+    app.on_staking_pool_account_impl(pool.clone(), StakingAction::Withdraw)
+        .expect("callback should work");
+    assert_eq!(app.staking_summary()[&pool].rewards.0, 0);
+    assert_eq!(app.hot(), 0);
+    assert!(events().is_empty());
+}
+
+#[test]
+fn test_migrate_config() {
+    let mut app = install();
+    fast_forward(100, 1000);
+    let hot_key = &CONFIG.hot_keys[0];
+    let tier_hot = 1000 * hot_key.nano_near_per_second * 10u128.pow(15);
+    let tier_key: PublicKey = hot_key.public_key.parse().unwrap();
+    let old_key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtq"
+        .parse()
+        .unwrap();
+    set_signer_key(&tier_key);
+    app.lock_impl(50).expect("access should work");
+
+    // as if fungible tokens, trusted recipients and a hot key were added, and
+    // a hot key with a pending payment was removed from the config
+    app.trusted_allowances.clear();
+    app.ft_allowances.truncate(1);
+    let tier = app.key_tiers.remove(&tier_key).unwrap();
+    app.key_tiers.insert(old_key.clone(), KeyTier::new(1, 1));
+    app.key_tiers.insert(tier_key.clone(), tier);
+    app.pending_payments.push(PendingPayment {
+        id: 0,
+        receiver: "max.near".parse().unwrap(),
+        yocto: U128(100),
+        release_at: U64(0),
+        budget: Budget::Key(old_key.clone()),
+        approvals: vec![],
+        approvals_required: 0,
+    });
+    env::state_write(&app);
+
+    next_call();
+    let app = Teller::migrate();
+    assert_eq!(
+        app.trusted_allowances.len(),
+        CONFIG.trusted_recipients.len()
+    );
+    assert_eq!(app.ft_allowances.len(), CONFIG.fungible_tokens.len());
+    assert_eq!(app.key_tiers.len(), CONFIG.hot_keys.len());
+    assert_eq!(app.hot_for_key(tier_key), tier_hot - 50);
+    assert!(app.pending_payments()[0].budget == Budget::Hot);
+}
+
+#[test]
+fn test_migrate_key_rate() {
+    let mut app = install();
+    fast_forward(100, 1000);
+    let hot_key = &CONFIG.hot_keys[0];
+    let tier_hot = 1000 * hot_key.nano_near_per_second * 10u128.pow(15);
+    let tier_key: PublicKey = hot_key.public_key.parse().unwrap();
+    set_signer_key(&tier_key);
+    app.lock_impl(50).expect("access should work");
+
+    // as if the config lowered the rate of the key to a tenth
+    app.key_tiers
+        .get_mut(&tier_key)
+        .unwrap()
+        .nano_near_per_second *= 10;
+    env::state_write(&app);
+
+    // what accrued at the old rate is kept, the new rate applies from now on
+    next_call();
+    let app = Teller::migrate();
+    assert_eq!(app.hot_for_key(tier_key.clone()), 10 * tier_hot - 50);
+    fast_forward(100, 1000);
+    assert_eq!(app.hot_for_key(tier_key), 11 * tier_hot - 50);
+}
+
+#[test]
+fn test_migrate_access() {
+    install();
+    set_predecessor_account("max.near", false);
+    let err = Teller::migrate_impl().err().expect("should fail");
+    assert_eq!(err, Error::ForeignAccountNotAllowed);
+}
+
 fn get_context(is_view: bool) -> VMContext {
     let account_id: AccountId = "teller.near".parse().unwrap();
     VMContextBuilder::new()
@@ -195,6 +1577,14 @@ fn seconds_to_yocto(seconds: u64) -> u128 {
     seconds as u128 * super::CONFIG.nano_near_per_second * 10u128.pow(15)
 }
 
+fn trusted_recipient() -> AccountId {
+    CONFIG.trusted_recipients[0].account_id.parse().unwrap()
+}
+
+fn trusted_seconds_to_yocto(seconds: u64) -> u128 {
+    seconds as u128 * CONFIG.trusted_recipients[0].nano_near_per_second * 10u128.pow(15)
+}
+
 fn yocto_to_near(yocto: Balance) -> Near {
     (yocto / 10u128.pow(24)) as u32
 }
@@ -211,6 +1601,11 @@ fn fast_forward(blocks: u64, seconds: u64) {
     testing_env!(context);
 }
 
+/// Start a new function call at the same block, with fresh gas and logs.
+fn next_call() {
+    fast_forward(0, 0);
+}
+
 fn set_predecessor_account(account_id: &str, is_view: bool) {
     let mut context = get_context(is_view);
     context.block_timestamp = env::block_timestamp();
@@ -219,7 +1614,104 @@ fn set_predecessor_account(account_id: &str, is_view: bool) {
     testing_env!(context);
 }
 
+fn set_signer_key(public_key: &PublicKey) {
+    let mut context = get_context(false);
+    context.block_timestamp = env::block_timestamp();
+    context.block_index = env::block_height();
+    context.signer_account_pk = public_key.clone();
+    testing_env!(context);
+}
+
+fn set_attached_deposit(yocto: Balance) {
+    let mut context = get_context(false);
+    context.block_timestamp = env::block_timestamp();
+    context.block_index = env::block_height();
+    context.predecessor_account_id = env::predecessor_account_id();
+    context.attached_deposit = yocto;
+    testing_env!(context);
+}
+
+fn set_callback_result(result: PromiseResult) {
+    let mut context = get_context(false);
+    context.block_timestamp = env::block_timestamp();
+    context.block_index = env::block_height();
+    testing_env!(
+        context,
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![result],
+    );
+}
+
+/// Simulate the result of `get_account` on a staking pool.
+fn set_pool_account_result(staked: Balance, unstaked: Balance) {
+    set_pool_account_result_can_withdraw(staked, unstaked, true);
+}
+
+fn set_pool_account_result_can_withdraw(staked: Balance, unstaked: Balance, can_withdraw: bool) {
+    let account = format!(
+        r#"{{"account_id":"teller.near","unstaked_balance":"{unstaked}","staked_balance":"{staked}","can_withdraw":{can_withdraw}}}"#
+    );
+    set_callback_result(PromiseResult::Successful(account.into_bytes()));
+}
+
+/// NEP-297 events logged so far, parsed from JSON.
+fn events() -> Vec<serde_json::Value> {
+    get_logs()
+        .iter()
+        .map(|log| {
+            let json = log.strip_prefix("EVENT_JSON:").expect("not an event");
+            serde_json::from_str(json).expect("invalid event JSON")
+        })
+        .collect()
+}
+
+/// Function calls to staking pools scheduled so far, without callbacks.
+fn staking_pool_calls() -> Vec<(String, String)> {
+    get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| receipt.actions)
+        .filter_map(|action| match action {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => Some((function_name, String::from_utf8(args).unwrap())),
+            _ => None,
+        })
+        .filter(|(function_name, _)| {
+            !function_name.starts_with("on_") && function_name != "get_account"
+        })
+        .collect()
+}
+
 impl Teller {
+    /// Go through the callbacks of a successful `stake`.
+    fn simulate_stake(&mut self, pool: &AccountId, yocto: Balance) {
+        let action = StakingAction::Stake(U128(yocto));
+        set_callback_result(PromiseResult::Successful(vec![]));
+        self.on_staking_action_impl(pool.clone(), action)
+            .expect("callback should work");
+        set_pool_account_result(yocto, 0);
+        self.on_staking_pool_account_impl(pool.clone(), action)
+            .expect("callback should work");
+    }
+
+    /// Compare [deposited, unstaked, withdrawn, staked_balance, unstaked_balance].
+    #[track_caller]
+    fn assert_staking_record(&self, pool: &AccountId, expected: [Balance; 5]) {
+        let record = &self.staking_summary()[pool];
+        let actual = [
+            record.deposited.0,
+            record.unstaked.0,
+            record.withdrawn.0,
+            record.staked_balance.0,
+            record.unstaked_balance.0,
+        ];
+        assert_eq!(actual, expected);
+    }
+
     #[track_caller]
     fn assert_hot(&self, seconds: u64) {
         assert_eq!(self.hot(), seconds_to_yocto(seconds));
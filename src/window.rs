@@ -0,0 +1,55 @@
+//! Limit on how much can be spent within any 24 hours.
+//!
+//! Spending is summed up in hourly buckets, which keeps the state small no
+//! matter how many payments are made. Buckets are kept for 25 hours, which
+//! makes the limit slightly stricter than necessary but guarantees that no
+//! window of 24 hours ever sees more than the limit.
+
+use crate::error::Error;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{env, Balance};
+
+const BUCKET_NS: u64 = 3600 * 1_000_000_000;
+const NUM_BUCKETS: u64 = 25;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub(crate) struct SpendingWindow {
+    /// yocto NEAR spent per hour, oldest first. Hours are counted since unix epoch.
+    buckets: Vec<(u64, Balance)>,
+}
+
+impl SpendingWindow {
+    /// yocto NEAR spent within the current window.
+    pub(crate) fn spent(&self) -> Balance {
+        let first_hour = Self::current_hour().saturating_sub(NUM_BUCKETS - 1);
+        self.buckets
+            .iter()
+            .filter(|(hour, _)| *hour >= first_hour)
+            .map(|(_, yocto)| yocto)
+            .sum()
+    }
+
+    /// Fail if spending `yocto` now would exceed `max` within the window.
+    pub(crate) fn check(&self, yocto: Balance, max: Balance) -> Result<(), Error> {
+        if self.spent() + yocto > max {
+            Err(Error::DailyLimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Add spending to the current hour and forget hours outside the window.
+    pub(crate) fn record(&mut self, yocto: Balance) {
+        let hour = Self::current_hour();
+        let first_hour = hour.saturating_sub(NUM_BUCKETS - 1);
+        self.buckets.retain(|(h, _)| *h >= first_hour);
+        match self.buckets.last_mut() {
+            Some((h, spent)) if *h == hour => *spent += yocto,
+            _ => self.buckets.push((hour, yocto)),
+        }
+    }
+
+    fn current_hour() -> u64 {
+        env::block_timestamp() / BUCKET_NS
+    }
+}
@@ -86,6 +86,96 @@ async fn test_init() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_pay_nonexistent_account() -> anyhow::Result<()> {
+    let worker = workspaces::sandbox().await?;
+    let contract = init(&worker).await?;
+
+    let res = contract.call("hot").view().await?;
+    let hot_before: u128 = std::str::from_utf8(&res.result)?.parse()?;
+
+    // named accounts are not created by a transfer, so this transfer fails
+    let pay_res = contract
+        .call("pay_yocto")
+        .args_json(json!({
+            "yocto": hot_before.to_string(),
+            "a": "does-not-exist.test.near",
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(pay_res.is_success(), "{pay_res:?}");
+    assert!(!pay_res.receipt_failures().is_empty(), "{pay_res:?}");
+
+    // the payment was charged and then given back
+    let refunds: Vec<serde_json::Value> = pay_res
+        .logs()
+        .iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .map(|json| serde_json::from_str::<serde_json::Value>(json).unwrap())
+        .filter(|event| event["event"] == "payment_refunded")
+        .collect();
+    assert_eq!(refunds.len(), 1, "{pay_res:?}");
+    assert_eq!(refunds[0]["data"]["yocto"], hot_before.to_string());
+    let res = contract.call("hot").view().await?;
+    let hot_after: u128 = std::str::from_utf8(&res.result)?.parse()?;
+    assert!(hot_after >= hot_before, "{pay_res:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_inheritance() -> anyhow::Result<()> {
+    let worker = workspaces::sandbox().await?;
+    // inheritance is disabled in config.ron, the sandbox build enables it
+    let wasm = std::fs::read("res/near_teller_sandbox.wasm")?;
+    let contract = worker.dev_deploy(&wasm).await?;
+    let res = contract.call("init").max_gas().transact().await?;
+    assert!(res.is_success(), "{res:?}");
+    // must match `beneficiary` of the test configuration in lib.rs
+    let heir = worker
+        .root_account()?
+        .create_subaccount("heir")
+        .transact()
+        .await?
+        .into_result()?;
+
+    // too early, the owner was active at init
+    let res = heir
+        .call(contract.id(), "claim_inheritance")
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_failure(), "{res:?}");
+
+    // a heartbeat shortly before the period ends restarts it
+    worker.fast_forward(3000).await?;
+    let res = contract.call("heartbeat").max_gas().transact().await?;
+    assert!(res.is_success(), "{res:?}");
+    worker.fast_forward(3000).await?;
+    let res = heir
+        .call(contract.id(), "claim_inheritance")
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_failure(), "{res:?}");
+
+    // after `inactivity_seconds` without heartbeat, the heir gets the balance
+    worker.fast_forward(10_000).await?;
+    let heir_before = heir.view_account().await?.balance;
+    let teller_before = contract.view_account().await?.balance;
+    let res = heir
+        .call(contract.id(), "claim_inheritance")
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success(), "{res:?}");
+    let heir_after = heir.view_account().await?.balance;
+    assert!(heir_after > heir_before + teller_before / 2, "{res:?}");
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_stake() -> anyhow::Result<()> {
     let worker = workspaces::testnet_archival().await?;